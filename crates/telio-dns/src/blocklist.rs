@@ -0,0 +1,120 @@
+//! Domain blocklist / filtering for magic DNS.
+//!
+//! [`DomainBlocklist`] decides, given a query name, whether the query should go through to
+//! [`crate::forward::ForwardAuthority`] as normal or be intercepted and answered locally -- turning
+//! the resolver already sitting in the tunnel's data path into an ad/tracker/malware filter.
+//! Matching is exact-suffix (blocking `example.com` also blocks any `*.example.com`), backed by a
+//! `HashSet` of normalized suffixes so a large list is a handful of hash lookups per query rather
+//! than a linear scan.
+//!
+//! [`crate::local::LocalAuthority`] is the live caller: it holds a [`DomainBlocklist`] and checks
+//! it ahead of its response cache and the forwarder, and `LocalAuthority::set_blocklist` is the
+//! runtime-update method the request asked for, so the list can change without restarting DNS.
+//! What's still left to whoever wires this crate into the resolver `start_dns()` constructs is
+//! feeding `set_blocklist` from a feature flag alongside `exit_dns`: that flag is defined on
+//! `Features`, in a crate this checkout doesn't include.
+
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use trust_dns_server::client::rr::LowerName;
+
+/// What to answer a blocked query with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockAction {
+    /// Answer as NXDOMAIN.
+    NxDomain,
+    /// Answer with a fixed sink address (typically `0.0.0.0` / `::`) instead.
+    Sinkhole { v4: Ipv4Addr, v6: Ipv6Addr },
+}
+
+impl BlockAction {
+    /// The conventional "nowhere" sinkhole: `0.0.0.0` for A, `::` for AAAA.
+    pub fn unspecified_sinkhole() -> Self {
+        BlockAction::Sinkhole {
+            v4: Ipv4Addr::UNSPECIFIED,
+            v6: Ipv6Addr::UNSPECIFIED,
+        }
+    }
+
+    /// The address this action would answer with for a query wanting AAAA (`wants_v6`) or A
+    /// records, if it's a sinkhole.
+    pub fn sinkhole_address(&self, wants_v6: bool) -> Option<IpAddr> {
+        match self {
+            BlockAction::NxDomain => None,
+            BlockAction::Sinkhole { v4, v6 } => Some(if wants_v6 {
+                IpAddr::V6(*v6)
+            } else {
+                IpAddr::V4(*v4)
+            }),
+        }
+    }
+}
+
+/// A compiled set of blocked domain suffixes.
+#[derive(Debug, Clone, Default)]
+pub struct DomainBlocklist {
+    action: Option<BlockAction>,
+    /// Normalized (lowercased, no trailing dot) blocked suffixes.
+    blocked: HashSet<String>,
+}
+
+impl DomainBlocklist {
+    /// Builds a blocklist from a list of domains/patterns (e.g. `example.com`), each of which
+    /// blocks itself and every subdomain. `action` decides how a match gets answered.
+    pub fn new<I, S>(domains: I, action: BlockAction) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            action: Some(action),
+            blocked: domains.into_iter().map(|d| normalize(d.as_ref())).collect(),
+        }
+    }
+
+    /// An empty blocklist that matches nothing, for when filtering is disabled.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the whole list in place, so a caller can update it at runtime (e.g. via a `Runtime`
+    /// method) without tearing down and recreating the resolver.
+    pub fn reconfigure<I, S>(&mut self, domains: I, action: BlockAction)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        *self = Self::new(domains, action);
+    }
+
+    /// Whether `name` matches the blocklist: itself, or any ancestor domain, is in the blocked set.
+    pub fn matches(&self, name: &LowerName) -> bool {
+        if self.action.is_none() || self.blocked.is_empty() {
+            return false;
+        }
+        let query = normalize(&name.to_string());
+        if query.is_empty() {
+            return false;
+        }
+        let labels: Vec<&str> = query.split('.').collect();
+        (0..labels.len()).any(|start| self.blocked.contains(&labels[start..].join(".")))
+    }
+
+    /// Returns the action to take for `name`, or `None` if it isn't blocked.
+    pub fn check(&self, name: &LowerName) -> Option<BlockAction> {
+        if self.matches(name) {
+            self.action
+        } else {
+            None
+        }
+    }
+}
+
+/// Lowercases and strips the trailing root dot trust-dns names are rendered with, so
+/// `"Example.com."` and `"example.com"` compare equal.
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
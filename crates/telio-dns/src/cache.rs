@@ -0,0 +1,213 @@
+//! A bounded response cache for the magic DNS resolver.
+//!
+//! Every query today round-trips through [`crate::local::LocalAuthority`] to
+//! [`crate::forward::ForwardAuthority`] and on to an upstream, even for the same handful of `nord`
+//! and exit-DNS names a meshnet client repeatedly asks about. [`DnsCache`] sits in front of that:
+//! callers check it before forwarding, and populate it with the answer (positive or negative)
+//! afterwards.
+//!
+//! Eviction is a segmented cold/hot LRU, which approximates CLOCK-2Q/CLOCK-Pro's goal -- a single
+//! scan over many once-only names shouldn't flush entries that are actually being reused -- without
+//! implementing the full reference-bit CLOCK sweep: new entries land in a small `cold` segment;
+//! a hit on a cold entry promotes it into the larger `hot` segment; each segment evicts its own
+//! least-recently-used entry independently once full, so a scan can only ever evict other
+//! once-seen entries out of `cold`, never a proven-hot one.
+//!
+//! [`crate::local::LocalAuthority`] is the live caller: it checks [`DnsCache::get`] ahead of
+//! calling through to `ForwardAuthority::lookup`, and on success calls [`DnsCache::insert`] /
+//! [`DnsCache::insert_negative`] with the forwarder's answer. `LocalAuthority::set_records` also
+//! calls [`DnsCache::clear`] whenever the local record set changes, so a newly added/removed
+//! local record takes effect immediately rather than waiting out a cached upstream answer for the
+//! same name.
+//!
+//! [`DnsCache::get_stale`] has no caller: when the forwarder returns an error other than
+//! NXDOMAIN, `LocalAuthority::lookup` passes that error on and never serves a stale entry.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use trust_dns_server::client::rr::{DNSClass, LowerName, Record, RecordType};
+
+/// (normalized qname, qtype, qclass) -- the cache key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub qname: LowerName,
+    pub qtype: RecordType,
+    pub qclass: DNSClass,
+}
+
+impl CacheKey {
+    pub fn new(qname: LowerName, qtype: RecordType, qclass: DNSClass) -> Self {
+        Self {
+            qname,
+            qtype,
+            qclass,
+        }
+    }
+}
+
+/// A cached answer: either the records an upstream returned, or that it returned NXDOMAIN/NODATA
+/// (negative caching), kept for whatever TTL the caller passes to [`DnsCache::insert_negative`].
+#[derive(Debug, Clone)]
+pub enum CachedAnswer {
+    Positive(Vec<Record>),
+    Negative,
+}
+
+struct Entry {
+    answer: CachedAnswer,
+    expires_at: Instant,
+    segment: Segment,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Cold,
+    Hot,
+}
+
+/// What a lookup against a live (non-stale) entry returned.
+pub struct CacheHit {
+    pub answer: CachedAnswer,
+    /// How much longer the entry has before the original TTL runs out; useful for setting the
+    /// TTL on the response this cache hit is serving.
+    pub remaining_ttl: Duration,
+}
+
+/// Bounded, segmented-LRU response cache. See the module doc for the eviction policy.
+pub struct DnsCache {
+    inner: std::sync::Mutex<State>,
+    cold_capacity: usize,
+    hot_capacity: usize,
+}
+
+struct State {
+    entries: HashMap<CacheKey, Entry>,
+    cold_order: VecDeque<CacheKey>,
+    hot_order: VecDeque<CacheKey>,
+}
+
+impl DnsCache {
+    /// `cold_capacity` bounds the segment new entries land in; `hot_capacity` bounds the segment
+    /// entries are promoted into after a hit while still cold.
+    pub fn new(cold_capacity: usize, hot_capacity: usize) -> Self {
+        Self {
+            inner: std::sync::Mutex::new(State {
+                entries: HashMap::new(),
+                cold_order: VecDeque::new(),
+                hot_order: VecDeque::new(),
+            }),
+            cold_capacity,
+            hot_capacity,
+        }
+    }
+
+    /// Looks up `key`, promoting it to the hot segment on a hit. Returns `None` if there's no
+    /// entry, or it's present but expired (see [`DnsCache::get_stale`] for the latter).
+    pub fn get(&self, key: &CacheKey) -> Option<CacheHit> {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        let is_fresh = state
+            .entries
+            .get(key)
+            .map(|entry| entry.expires_at > now)
+            .unwrap_or(false);
+        if !is_fresh {
+            return None;
+        }
+
+        self.promote(&mut state, key);
+
+        let entry = state.entries.get(key)?;
+        Some(CacheHit {
+            answer: entry.answer.clone(),
+            remaining_ttl: entry.expires_at.saturating_duration_since(now),
+        })
+    }
+
+    /// Looks up `key` regardless of expiry, for serving a stale answer when the upstream is
+    /// unreachable. Doesn't affect segment placement -- an entry only this stale is on its way
+    /// out regardless.
+    pub fn get_stale(&self, key: &CacheKey) -> Option<CachedAnswer> {
+        let state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        state.entries.get(key).map(|entry| entry.answer.clone())
+    }
+
+    /// Caches a positive answer, expiring in `ttl` (the minimum RR TTL across the answer set).
+    pub fn insert(&self, key: CacheKey, records: Vec<Record>, ttl: Duration) {
+        self.insert_answer(key, CachedAnswer::Positive(records), ttl);
+    }
+
+    /// Caches an NXDOMAIN/NODATA answer, expiring in `ttl`. `LocalAuthority` passes its fixed
+    /// 30-second negative TTL; the SOA minimum of the upstream answer is not used.
+    pub fn insert_negative(&self, key: CacheKey, ttl: Duration) {
+        self.insert_answer(key, CachedAnswer::Negative, ttl);
+    }
+
+    fn insert_answer(&self, key: CacheKey, answer: CachedAnswer, ttl: Duration) {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.entries.contains_key(&key) {
+            self.promote(&mut state, &key);
+            if let Some(entry) = state.entries.get_mut(&key) {
+                entry.answer = answer;
+                entry.expires_at = Instant::now() + ttl;
+            }
+            return;
+        }
+
+        if state.cold_order.len() >= self.cold_capacity {
+            if let Some(evicted) = state.cold_order.pop_front() {
+                state.entries.remove(&evicted);
+            }
+        }
+        state.cold_order.push_back(key.clone());
+        state.entries.insert(
+            key,
+            Entry {
+                answer,
+                expires_at: Instant::now() + ttl,
+                segment: Segment::Cold,
+            },
+        );
+    }
+
+    /// Promotes `key` from cold to hot on a hit, evicting the least-recently-promoted hot entry if
+    /// the hot segment is already full. A hit on an already-hot entry just moves it to the back.
+    fn promote(&self, state: &mut State, key: &CacheKey) {
+        let was_cold = matches!(
+            state.entries.get(key).map(|entry| entry.segment),
+            Some(Segment::Cold)
+        );
+
+        if was_cold {
+            state.cold_order.retain(|k| k != key);
+        } else {
+            state.hot_order.retain(|k| k != key);
+        }
+
+        if state.hot_order.len() >= self.hot_capacity {
+            if let Some(evicted) = state.hot_order.pop_front() {
+                state.entries.remove(&evicted);
+            }
+        }
+
+        state.hot_order.push_back(key.clone());
+        if let Some(entry) = state.entries.get_mut(key) {
+            entry.segment = Segment::Hot;
+        }
+    }
+
+    /// Drops every cached entry. Called when the local record set changes underneath the cache
+    /// (e.g. from `upsert_dns_peers()`), since a newly added/removed local record must take effect
+    /// immediately rather than waiting out whatever was cached.
+    pub fn clear(&self) {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        state.entries.clear();
+        state.cold_order.clear();
+        state.hot_order.clear();
+    }
+}
@@ -0,0 +1,226 @@
+//! Transport tagging and DNSCrypt v2 stamp parsing for encrypted upstream DNS.
+//!
+//! `ForwardAuthority::build_resolver` already passes `Protocol::Https`/`Protocol::Tls` name
+//! servers straight through to `trust-dns-resolver`, which speaks DoH/DoT natively -- so an
+//! upstream entry already carrying one of those protocols gets an encrypted, authenticated path
+//! for free. DNSCrypt v2 has no such built-in support, so this module gives a caller (e.g. the
+//! resolver constructed by `start_dns()`) a way to describe a DNSCrypt upstream and parse its
+//! `sdns://` stamp.
+//!
+//! Unlike [`crate::blocklist`]/[`crate::cache`], this one genuinely has no caller to wire up in
+//! this checkout, and can't get one: what's missing isn't a constructor or a registration point,
+//! it's the DNSCrypt X25519/XSalsa20 handshake itself, which needs a vetted crypto dependency
+//! (e.g. `x25519-dalek` + `crypto_box`) that isn't available here. Rolling a hand-written
+//! implementation of those primitives instead would be a bigger risk than the gap it closes, so
+//! this module stops at the primitive: parsing the stamp and padding the plaintext query.
+//! Whoever adds that dependency is the one who wires `UpstreamTransport::DnsCrypt` into
+//! `ForwardAuthority::build_resolver`: take the [`DnsCryptStamp`] parsed here, perform the
+//! handshake using that dependency, and use [`pad_query`] on the resulting plaintext query before
+//! encrypting it, per the DNSCrypt spec.
+
+use std::fmt;
+
+/// How an upstream DNS query should be transported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpstreamTransport {
+    /// Plain UDP/TCP on port 53, today's behavior.
+    Do53,
+    /// DNS-over-HTTPS. `bootstrap_ip` lets the caller reach `url`'s host without a prior plaintext
+    /// DNS lookup.
+    Doh {
+        url: String,
+        bootstrap_ip: Option<std::net::IpAddr>,
+    },
+    /// DNSCrypt v2, identified by its `sdns://` stamp.
+    DnsCrypt(DnsCryptStamp),
+}
+
+/// A parsed DNSCrypt v2 `sdns://` stamp: the provider's name and long-term public key, which
+/// together are enough to perform the client-side X25519 handshake (not implemented here, see the
+/// module doc).
+#[derive(Clone, PartialEq, Eq)]
+pub struct DnsCryptStamp {
+    /// The resolver address the stamp points at (`ip:port`).
+    pub address: String,
+    /// Provider name used both to authenticate the certificate and as the SNI-equivalent.
+    pub provider_name: String,
+    /// The provider's Ed25519 public key, used to verify the certificate that carries the actual
+    /// X25519 key used for the handshake.
+    pub public_key: [u8; 32],
+}
+
+impl fmt::Debug for DnsCryptStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut public_key_hex = String::with_capacity(self.public_key.len() * 2);
+        for byte in self.public_key {
+            public_key_hex.push_str(&format!("{:02x}", byte));
+        }
+        f.debug_struct("DnsCryptStamp")
+            .field("address", &self.address)
+            .field("provider_name", &self.provider_name)
+            .field("public_key", &public_key_hex)
+            .finish()
+    }
+}
+
+/// Errors parsing an `sdns://` stamp.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum StampError {
+    #[error("stamp is missing the 'sdns://' prefix")]
+    MissingPrefix,
+    #[error("stamp payload is not valid unpadded base64url: {0}")]
+    InvalidBase64(String),
+    #[error("stamp payload is too short to contain a valid DNSCrypt record")]
+    Truncated,
+    #[error("stamp's protocol identifier byte {0:#04x} is not DNSCrypt (0x01)")]
+    WrongProtocol(u8),
+}
+
+const DNSCRYPT_PROTOCOL_ID: u8 = 0x01;
+
+/// Parses a DNSCrypt v2 stamp of the form `sdns://<base64url(protocol || props || len-prefixed
+/// address || pubkey(32) || len-prefixed provider_name)>`, per the DNSCrypt stamp spec.
+pub fn parse_stamp(stamp: &str) -> Result<DnsCryptStamp, StampError> {
+    let payload = stamp.strip_prefix("sdns://").ok_or(StampError::MissingPrefix)?;
+    let bytes = base64_url_decode(payload).map_err(StampError::InvalidBase64)?;
+
+    // protocol (1) + properties bitfield (8) + at least one empty length-prefixed string (1) +
+    // public key (32) + at least one empty length-prefixed string (1)
+    if bytes.len() < 1 + 8 + 1 + 32 + 1 {
+        return Err(StampError::Truncated);
+    }
+
+    let protocol = bytes[0];
+    if protocol != DNSCRYPT_PROTOCOL_ID {
+        return Err(StampError::WrongProtocol(protocol));
+    }
+
+    let (address, rest) = read_length_prefixed_string(&bytes[9..])?;
+
+    let public_key_bytes = rest.get(..32).ok_or(StampError::Truncated)?;
+    let mut public_key = [0u8; 32];
+    public_key.copy_from_slice(public_key_bytes);
+
+    let (provider_name, _rest) = read_length_prefixed_string(&rest[32..])?;
+
+    Ok(DnsCryptStamp {
+        address,
+        provider_name,
+        public_key,
+    })
+}
+
+fn read_length_prefixed_string(bytes: &[u8]) -> Result<(String, &[u8]), StampError> {
+    let len = *bytes.first().ok_or(StampError::Truncated)? as usize;
+    let value = bytes.get(1..1 + len).ok_or(StampError::Truncated)?;
+    let rest = bytes.get(1 + len..).ok_or(StampError::Truncated)?;
+    Ok((String::from_utf8_lossy(value).into_owned(), rest))
+}
+
+/// Minimal unpadded base64url decoder (the alphabet DNSCrypt stamps use), so this module doesn't
+/// need to pull in a whole base64 crate for one field.
+fn base64_url_decode(input: &str) -> Result<Vec<u8>, String> {
+    fn value(byte: u8) -> Result<u8, String> {
+        match byte {
+            b'A'..=b'Z' => Ok(byte - b'A'),
+            b'a'..=b'z' => Ok(byte - b'a' + 26),
+            b'0'..=b'9' => Ok(byte - b'0' + 52),
+            b'-' => Ok(62),
+            b'_' => Ok(63),
+            _ => Err(format!("invalid base64url byte: {}", byte as char)),
+        }
+    }
+
+    let input = input.as_bytes();
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut buf = [0u8; 4];
+        for (i, &byte) in chunk.iter().enumerate() {
+            buf[i] = value(byte)?;
+        }
+        out.push((buf[0] << 2) | (buf[1] >> 4));
+        if chunk.len() > 2 {
+            out.push((buf[1] << 4) | (buf[2] >> 2));
+        }
+        if chunk.len() > 3 {
+            out.push((buf[2] << 6) | buf[3]);
+        }
+    }
+    Ok(out)
+}
+
+/// Pads `query` to a multiple of `block_size` bytes per the DNSCrypt padding scheme: a `0x80`
+/// byte followed by zeroes, so the ciphertext length doesn't leak the exact query length.
+/// `block_size` is 64 for UDP queries and 472 for TCP, per the spec.
+pub fn pad_query(query: &[u8], block_size: usize) -> Vec<u8> {
+    let mut padded = query.to_vec();
+    padded.push(0x80);
+    let remainder = padded.len() % block_size;
+    if remainder != 0 {
+        padded.resize(padded.len() + (block_size - remainder), 0);
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stamp_with_nonempty_address() {
+        // protocol=0x01, props=0, addr="212.47.228.136:443", pk=0..32, provider_name=
+        // "2.dnscrypt-cert.fr", laid out per the spec as
+        // protocol || props || addr || pk || provider_name.
+        let stamp = "sdns://AQAAAAAAAAAAEjIxMi40Ny4yMjguMTM2OjQ0MwABAgMEBQYHCAkKCwwNDg8QERITFBUW\
+                     FxgZGhscHR4fEjIuZG5zY3J5cHQtY2VydC5mcg";
+        let parsed = parse_stamp(stamp).unwrap();
+        assert_eq!(parsed.address, "212.47.228.136:443");
+        assert_eq!(parsed.provider_name, "2.dnscrypt-cert.fr");
+        let expected_key: Vec<u8> = (0u8..32).collect();
+        assert_eq!(parsed.public_key.to_vec(), expected_key);
+    }
+
+    #[test]
+    fn parses_stamp_with_empty_address() {
+        // Some stamps omit the addr field (resolved via the provider name instead).
+        let stamp =
+            "sdns://AQAAAAAAAAAAAKqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqC2V4YW1wbGUuY29t";
+        let parsed = parse_stamp(stamp).unwrap();
+        assert_eq!(parsed.address, "");
+        assert_eq!(parsed.provider_name, "example.com");
+        assert_eq!(parsed.public_key, [0xAAu8; 32]);
+    }
+
+    #[test]
+    fn rejects_missing_prefix() {
+        assert_eq!(parse_stamp("not-a-stamp"), Err(StampError::MissingPrefix));
+    }
+
+    #[test]
+    fn rejects_wrong_protocol() {
+        // Same payload as `parses_stamp_with_empty_address`, but protocol byte set to 0x02.
+        let stamp =
+            "sdns://AgAAAAAAAAAAAKqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqC2V4YW1wbGUuY29t";
+        assert_eq!(parse_stamp(stamp), Err(StampError::WrongProtocol(0x02)));
+    }
+
+    #[test]
+    fn rejects_truncated_payload() {
+        let stamp = "sdns://AQAAAAAAAAAA";
+        assert_eq!(parse_stamp(stamp), Err(StampError::Truncated));
+    }
+
+    #[test]
+    fn pad_query_rounds_up_to_block_size() {
+        let padded = pad_query(b"hello", 8);
+        assert_eq!(padded.len(), 8);
+        assert_eq!(&padded[5..], &[0x80, 0, 0]);
+    }
+
+    #[test]
+    fn pad_query_adds_a_full_block_when_already_aligned() {
+        let padded = pad_query(b"12345678", 8);
+        assert_eq!(padded.len(), 16);
+        assert_eq!(padded[8], 0x80);
+    }
+}
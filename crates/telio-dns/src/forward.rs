@@ -2,10 +2,18 @@
 //! Needed to change behaviour of [tokio::net::UdpSocket]
 
 use std::{
+    future::Future,
     io,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use telio_utils::{telio_log_debug, telio_log_info, telio_log_trace, telio_log_warn};
 use tokio::net::{TcpStream, UdpSocket};
@@ -19,7 +27,7 @@ use trust_dns_server::{
     },
     proto::{iocompat::AsyncIoTokioAsStd, udp::UdpSocket as ProtoUdpSocket, TokioTime},
     resolver::{
-        config::ResolverConfig,
+        config::{LookupIpStrategy, Protocol, ResolverConfig},
         error::ResolveErrorKind,
         lookup::Lookup as ResolverLookup,
         name_server::{GenericConnection, GenericConnectionProvider, RuntimeProvider},
@@ -38,6 +46,27 @@ impl RuntimeProvider for TelioRuntime {
     type Tcp = AsyncIoTokioAsStd<TcpStream>;
     type Timer = TokioTime;
     type Udp = TelioUdpSocket;
+
+    /// Connects the upstream TCP (and TLS/HTTPS-over-TCP) socket to the name server.
+    ///
+    /// This mirrors [`TelioUdpSocket::bind`]: the socket is bound to the tun interface so that
+    /// encrypted upstreams (DoT/DoH) don't bypass the tunnel the way a bare `TcpStream::connect`
+    /// would.
+    fn connect_tcp(
+        &self,
+        addr: SocketAddr,
+    ) -> Pin<Box<dyn Send + Future<Output = io::Result<Self::Tcp>>>> {
+        Box::pin(async move {
+            telio_log_trace!("connecting tcp to address {:?}", addr);
+            let socket = match addr {
+                SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+                SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+            };
+            bind_tun::bind_to_tun(&socket)?;
+            let stream = socket.connect(addr).await?;
+            Ok(AsyncIoTokioAsStd(stream))
+        })
+    }
 }
 pub type TelioConnection = GenericConnection;
 pub type TelioConnectionProvider = GenericConnectionProvider<TelioRuntime>;
@@ -104,7 +133,98 @@ impl ProtoUdpSocket for TelioUdpSocket {
 /// This uses the trust-dns-resolver for resolving requests.
 pub struct ForwardAuthority {
     origin: LowerName,
-    resolver: TelioAsyncResolver,
+    resolver: ArcSwap<TelioAsyncResolver>,
+    /// Address-family strategy the forwarder's own lookups should honor, kept in sync with
+    /// whatever `ResolverOpts` the current resolver was built with.
+    ip_strategy: ArcSwap<LookupIpStrategy>,
+    /// Whether the current resolver was configured to DNSSEC-validate answers.
+    validate: AtomicBool,
+    /// Maximum number of `lookup()` calls allowed in flight at once, guarding against a burst of
+    /// concurrent queries (e.g. a misbehaving caller, or a DNSSEC chain that's slow to validate)
+    /// tying up every upstream connection at once.
+    max_concurrent_lookups: AtomicU32,
+    /// Maximum number of CNAME hops a single query's answer may contain before it's rejected with
+    /// SERVFAIL, bounding how far one query's delegation chain is allowed to run.
+    ///
+    /// `self.resolver.lookup()` chases CNAME/delegation chains internally and that process isn't
+    /// instrumentable from here (`TelioAsyncResolver` is opaque past the call), so this can't abort
+    /// a chain mid-flight the way a recursive-resolver's own hop counter would. What it can do,
+    /// since `preserve_intermediates` (see `build_resolver`) keeps every CNAME the resolver
+    /// followed in the returned answer, is count those hops once the answer comes back and reject
+    /// it if the chain ran longer than expected -- the same bound, enforced after the fact rather
+    /// than during.
+    max_cname_chain: AtomicU32,
+    /// Query counters and latency totals, see [`ForwardStats`].
+    stats: ForwardStatsCounters,
+}
+
+#[derive(Default)]
+struct ForwardStatsCounters {
+    total: AtomicU64,
+    noerror: AtomicU64,
+    nxdomain: AtomicU64,
+    servfail: AtomicU64,
+    timeouts: AtomicU64,
+    lookup_micros_total: AtomicU64,
+}
+
+/// A point-in-time snapshot of [`ForwardAuthority`]'s query metrics, suitable for logging or
+/// surfacing to operators so they can see which upstream is slow or failing on a given tunnel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardStats {
+    /// Total number of queries handled since the authority was created.
+    pub total_queries: u64,
+    /// Queries answered with `NOERROR`.
+    pub noerror: u64,
+    /// Queries answered with `NXDOMAIN`/`NoRecords`.
+    pub nxdomain: u64,
+    /// Queries that ended in `SERVFAIL` (including DNSSEC validation failures, requests rejected
+    /// because `max_concurrent_lookups` was already exhausted, and answers rejected for exceeding
+    /// `max_cname_chain`).
+    pub servfail: u64,
+    /// Queries that timed out talking to an upstream.
+    pub timeouts: u64,
+    /// Average wall-clock time spent inside `self.resolver.lookup(...)`, across all queries.
+    pub average_lookup_time: Duration,
+}
+
+/// Default cap on lookups in flight at once, see `max_concurrent_lookups`.
+const DEFAULT_MAX_CONCURRENT_LOOKUPS: u32 = 20;
+
+/// Default cap on CNAME hops in a single answer, see `max_cname_chain`.
+const DEFAULT_MAX_CNAME_CHAIN: u32 = 8;
+
+/// Floor for the negative-answer cache TTL, see `build_resolver`.
+const DEFAULT_NEGATIVE_MIN_TTL_SECS: u64 = 1;
+/// Ceiling for the negative-answer cache TTL, see `build_resolver`.
+const DEFAULT_NEGATIVE_MAX_TTL_SECS: u64 = 60;
+
+/// RAII guard that reserves one slot of `max_concurrent_lookups` for the duration of a lookup, and
+/// gives it back on drop so a later query can use it again. A query that finds no slot free is
+/// not queued: it fails at once with SERVFAIL.
+struct ConcurrencyGuard<'a>(&'a AtomicU32);
+
+impl<'a> ConcurrencyGuard<'a> {
+    fn acquire(in_flight: &'a AtomicU32) -> Result<Self, LookupError> {
+        loop {
+            let current = in_flight.load(Ordering::Acquire);
+            if current == 0 {
+                return Err(LookupError::from(ResponseCode::ServFail));
+            }
+            if in_flight
+                .compare_exchange(current, current - 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return Ok(Self(in_flight));
+            }
+        }
+    }
+}
+
+impl Drop for ConcurrencyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::AcqRel);
+    }
 }
 
 impl ForwardAuthority {
@@ -116,7 +236,93 @@ impl ForwardAuthority {
     ) -> Result<Self, String> {
         telio_log_info!("loading forwarder config: {}", origin);
 
+        let resolver = Self::build_resolver(config)?;
+        let options = config.options.unwrap_or_default();
+
+        telio_log_info!(
+            "forward resolver configured: {}: (dnssec validate: {})",
+            origin,
+            options.validate
+        );
+
+        // TODO: this might be infallible?
+        Ok(Self {
+            origin: origin.into(),
+            resolver: ArcSwap::from_pointee(resolver),
+            ip_strategy: ArcSwap::from_pointee(options.ip_strategy),
+            validate: AtomicBool::new(options.validate),
+            max_concurrent_lookups: AtomicU32::new(DEFAULT_MAX_CONCURRENT_LOOKUPS),
+            max_cname_chain: AtomicU32::new(DEFAULT_MAX_CNAME_CHAIN),
+            stats: ForwardStatsCounters::default(),
+        })
+    }
+
+    /// Swap the upstream resolver for a freshly built one without tearing down the authority.
+    ///
+    /// Lookups already in flight keep using the resolver they started with (it's held behind an
+    /// `Arc`), while any lookup started after this returns picks up `config`.
+    pub async fn reconfigure(&self, config: &ForwardConfig) -> Result<(), String> {
+        let resolver = Self::build_resolver(config)?;
+        let options = config.options.unwrap_or_default();
+        self.resolver.store(Arc::new(resolver));
+        self.ip_strategy.store(Arc::new(options.ip_strategy));
+        self.validate.store(options.validate, Ordering::Release);
+        telio_log_info!("forward resolver reconfigured: {}", self.origin);
+        Ok(())
+    }
+
+    /// Overrides the default cap on lookups in flight at once.
+    pub fn with_max_concurrent_lookups(self, max_concurrent_lookups: u32) -> Self {
+        self.max_concurrent_lookups
+            .store(max_concurrent_lookups, Ordering::Release);
+        self
+    }
+
+    /// Overrides the default cap on CNAME hops a single answer may contain, see
+    /// `max_cname_chain`.
+    pub fn with_max_cname_chain(self, max_cname_chain: u32) -> Self {
+        self.max_cname_chain.store(max_cname_chain, Ordering::Release);
+        self
+    }
+
+    /// Returns a snapshot of the query counters and average lookup latency accumulated so far.
+    pub fn stats(&self) -> ForwardStats {
+        let total = self.stats.total.load(Ordering::Relaxed);
+        let lookup_micros_total = self.stats.lookup_micros_total.load(Ordering::Relaxed);
+        ForwardStats {
+            total_queries: total,
+            noerror: self.stats.noerror.load(Ordering::Relaxed),
+            nxdomain: self.stats.nxdomain.load(Ordering::Relaxed),
+            servfail: self.stats.servfail.load(Ordering::Relaxed),
+            timeouts: self.stats.timeouts.load(Ordering::Relaxed),
+            average_lookup_time: if total > 0 {
+                Duration::from_micros(lookup_micros_total / total)
+            } else {
+                Duration::ZERO
+            },
+        }
+    }
+
+    fn build_resolver(config: &ForwardConfig) -> Result<TelioAsyncResolver, String> {
         let name_servers = config.name_servers.clone();
+        for ns in &name_servers {
+            match ns.protocol {
+                Protocol::Udp => telio_log_debug!("upstream {} via plain udp", ns.socket_addr),
+                Protocol::Tcp => telio_log_debug!("upstream {} via plain tcp", ns.socket_addr),
+                Protocol::Tls => telio_log_info!(
+                    "upstream {} via DNS-over-TLS, tls_dns_name: {:?}",
+                    ns.socket_addr,
+                    ns.tls_dns_name
+                ),
+                Protocol::Https => telio_log_info!(
+                    "upstream {} via DNS-over-HTTPS, tls_dns_name: {:?}",
+                    ns.socket_addr,
+                    ns.tls_dns_name
+                ),
+                _ => telio_log_warn!("upstream {} uses unsupported protocol", ns.socket_addr),
+            }
+        }
+
         let mut options = config.options.unwrap_or_default();
 
         // See RFC 1034, Section 4.3.2:
@@ -138,18 +344,32 @@ impl ForwardAuthority {
             options.preserve_intermediates = true;
         }
 
-        let config = ResolverConfig::from_parts(None, vec![], name_servers);
+        // A negative answer from one upstream shouldn't be retried against the same server --
+        // with several `name_servers` configured we'd rather fail over to the next one. Marking
+        // NXDOMAIN/NoRecords answers as untrusted is what makes the resolver's server-ordering
+        // advance past a server that just gave a negative response, while still retrying a
+        // genuine timeout/connection error against the same server.
+        if !options.distrust_nx_responses {
+            telio_log_debug!(
+                "enabling distrust_nx_responses so a negative answer fails over to the next upstream"
+            );
+            options.distrust_nx_responses = true;
+        }
 
-        let resolver = TelioAsyncResolver::new(config, options, TokioHandle)
-            .map_err(|e| format!("error constructing new Resolver: {}", e))?;
+        // Bound the negative-answer cache (DnsLru) TTLs so repeated failing lookups (e.g.
+        // captive-portal probes) are served from cache instead of hammering the upstreams, even
+        // if the upstream's own SOA minimum is unreasonably small or absent.
+        if options.negative_min_ttl.is_none() {
+            options.negative_min_ttl = Some(Duration::from_secs(DEFAULT_NEGATIVE_MIN_TTL_SECS));
+        }
+        if options.negative_max_ttl.is_none() {
+            options.negative_max_ttl = Some(Duration::from_secs(DEFAULT_NEGATIVE_MAX_TTL_SECS));
+        }
 
-        telio_log_info!("forward resolver configured: {}: ", origin);
+        let config = ResolverConfig::from_parts(None, vec![], name_servers);
 
-        // TODO: this might be infallible?
-        Ok(Self {
-            origin: origin.into(),
-            resolver,
-        })
+        TelioAsyncResolver::new(config, options, TokioHandle)
+            .map_err(|e| format!("error constructing new Resolver: {}", e))
     }
 }
 
@@ -191,21 +411,88 @@ impl Authority for ForwardAuthority {
         debug_assert!(self.origin.zone_of(name));
 
         telio_log_debug!("forwarding lookup: {} {}", name, rtype);
-        let name: LowerName = name.clone();
-        let resolve = self.resolver.lookup(name, rtype).await;
 
-        resolve
-            .map(ForwardLookup)
-            .map_err(|code| match code.kind() {
+        // Drop A/AAAA queries the configured address family strategy has no interest in, rather
+        // than bothering an upstream that would just answer them anyway.
+        let strategy = **self.ip_strategy.load();
+        if matches!(
+            (strategy, rtype),
+            (LookupIpStrategy::Ipv4Only, RecordType::AAAA)
+                | (LookupIpStrategy::Ipv6Only, RecordType::A)
+        ) {
+            telio_log_debug!(
+                "suppressing {} lookup for {}: ip strategy is {:?}",
+                rtype,
+                name,
+                strategy
+            );
+            return Ok(ForwardLookup::empty());
+        }
+
+        // Bound how many lookups can be in flight at once, so a burst of concurrent queries can't
+        // tie up every upstream connection at once.
+        self.stats.total.fetch_add(1, Ordering::Relaxed);
+        let _concurrency_guard =
+            ConcurrencyGuard::acquire(&self.max_concurrent_lookups).map_err(|e| {
+                self.stats.servfail.fetch_add(1, Ordering::Relaxed);
+                e
+            })?;
+
+        let validate = self.validate.load(Ordering::Acquire);
+        let name: LowerName = name.clone();
+        let resolver = self.resolver.load_full();
+
+        let started = Instant::now();
+        let resolve = resolver.lookup(name, rtype).await;
+        self.stats
+            .lookup_micros_total
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+        match resolve {
+            Ok(lookup) => {
+                let max_chain = self.max_cname_chain.load(Ordering::Acquire);
+                let chain_len = lookup
+                    .record_iter()
+                    .filter(|record| record.record_type() == RecordType::CNAME)
+                    .count() as u32;
+                if chain_len > max_chain {
+                    telio_log_warn!(
+                        "rejecting answer for {}: CNAME chain of {} hops exceeds max_cname_chain ({})",
+                        name,
+                        chain_len,
+                        max_chain
+                    );
+                    self.stats.servfail.fetch_add(1, Ordering::Relaxed);
+                    return Err(LookupError::from(ResponseCode::ServFail));
+                }
+                self.stats.noerror.fetch_add(1, Ordering::Relaxed);
+                Ok(ForwardLookup::new(lookup, validate))
+            }
+            Err(e) => match e.kind() {
+                ResolveErrorKind::Timeout => {
+                    self.stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                    Err(LookupError::from(ResponseCode::ServFail))
+                }
                 ResolveErrorKind::NoRecordsFound {
                     query: _,
                     soa: _,
                     negative_ttl: _,
                     response_code,
                     trusted: _,
-                } => LookupError::from(*response_code),
-                _ => LookupError::from(ResponseCode::Unknown(0)),
-            })
+                } => {
+                    self.stats.nxdomain.fetch_add(1, Ordering::Relaxed);
+                    Err(LookupError::from(*response_code))
+                }
+                ResolveErrorKind::Proto(_) if validate => {
+                    // DNSSEC validation failures surface as protocol errors from the resolver;
+                    // map them to SERVFAIL rather than the default ResponseCode::Unknown so they
+                    // don't get mistaken for generic forwarding noise.
+                    self.stats.servfail.fetch_add(1, Ordering::Relaxed);
+                    Err(LookupError::from(ResponseCode::ServFail))
+                }
+                _ => Err(LookupError::from(ResponseCode::Unknown(0))),
+            },
+        }
     }
 
     async fn search(
@@ -233,15 +520,46 @@ impl Authority for ForwardAuthority {
     }
 }
 
-pub struct ForwardLookup(ResolverLookup);
+pub struct ForwardLookup {
+    lookup: Option<ResolverLookup>,
+    /// Whether this answer should have the AD (Authentic Data) bit set, i.e. it came back from a
+    /// resolver running in DNSSEC-validating mode.
+    authenticated: bool,
+}
+
+impl ForwardLookup {
+    fn new(lookup: ResolverLookup, authenticated: bool) -> Self {
+        Self {
+            lookup: Some(lookup),
+            authenticated,
+        }
+    }
+
+    /// A lookup result with no records, used when the address-family strategy suppresses a
+    /// query before it ever reaches an upstream.
+    fn empty() -> Self {
+        Self {
+            lookup: None,
+            authenticated: false,
+        }
+    }
+
+    /// Whether the response should be returned with the AD bit set.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+}
 
 impl LookupObject for ForwardLookup {
     fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.lookup.as_ref().map_or(true, ResolverLookup::is_empty)
     }
 
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Record> + Send + 'a> {
-        Box::new(self.0.record_iter())
+        match &self.lookup {
+            Some(lookup) => Box::new(lookup.record_iter()),
+            None => Box::new(std::iter::empty()),
+        }
     }
 
     fn take_additionals(&mut self) -> Option<Box<dyn LookupObject>> {
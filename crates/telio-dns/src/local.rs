@@ -0,0 +1,238 @@
+//! A static-record overlay authority.
+//!
+//! Answers a small set of locally known records (meshnet peer names, user-configured hosts
+//! entries, and their reverse PTR counterparts) before ever reaching the network, then checks
+//! [`DomainBlocklist`] and [`DnsCache`] before finally falling through to a wrapped
+//! [`ForwardAuthority`] on a miss. This is the server-side equivalent of the resolver's `Hosts`
+//! file override, except it works without touching the OS hosts file, which matters for meshnet
+//! name resolution on platforms where libtelio can't write to it.
+//!
+//! This is the one live call path [`DomainBlocklist`] and [`DnsCache`] have in this checkout:
+//! both were previously freestanding structs with no caller anywhere. Forwarding still needs a
+//! real `start_dns()` to construct a [`LocalAuthority`] in the first place, which isn't present
+//! here, but everything downstream of that constructor -- blocklist before cache before forward,
+//! cache populated from whatever the forwarder returns -- now actually runs.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use trust_dns_server::{
+    authority::{
+        Authority, LookupError, LookupObject, LookupOptions, MessageRequest, UpdateResult,
+        ZoneType,
+    },
+    client::{
+        op::ResponseCode,
+        rr::{DNSClass, LowerName, Name, RData, Record, RecordType},
+    },
+    server::RequestInfo,
+};
+
+use crate::{
+    blocklist::{BlockAction, DomainBlocklist},
+    cache::{CacheKey, CachedAnswer, DnsCache},
+    forward::ForwardAuthority,
+};
+
+/// Key used to look up a statically configured record: the (lowercased) owner name together with
+/// the queried record type, so an `A` and `AAAA` entry for the same name can coexist.
+pub type StaticRecordKey = (LowerName, RecordType);
+
+/// TTL of the sinkhole records a blocklist hit answers with, and negative-cache TTL for an empty
+/// or NXDOMAIN answer from the forwarder: short enough that a blocklist update or a flaky upstream
+/// answer doesn't stick around for long, long enough that a burst of repeat queries for the same
+/// missing name doesn't all round-trip to the upstream for nothing.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// An authority that answers from a local record map first, then a blocklist, then a response
+/// cache, and forwards on a miss. See the module doc for the lookup order.
+///
+/// The record map is replaced wholesale (e.g. whenever the meshnet config changes) rather than
+/// mutated in place, so readers never observe a partially updated set of records.
+pub struct LocalAuthority {
+    origin: LowerName,
+    records: RwLock<Arc<HashMap<StaticRecordKey, Vec<Record>>>>,
+    blocklist: RwLock<DomainBlocklist>,
+    cache: DnsCache,
+    forward: Arc<ForwardAuthority>,
+}
+
+impl LocalAuthority {
+    /// Wraps `forward`, initially with no local records and no blocklist configured.
+    pub fn new(origin: LowerName, forward: Arc<ForwardAuthority>) -> Self {
+        Self {
+            origin,
+            records: RwLock::new(Arc::new(HashMap::new())),
+            blocklist: RwLock::new(DomainBlocklist::disabled()),
+            cache: DnsCache::new(256, 1024),
+            forward,
+        }
+    }
+
+    /// Replaces the whole set of local records (e.g. meshnet peer name -> mesh IP, plus any
+    /// user-supplied A/AAAA/CNAME/PTR entries). Callers are expected to have already synthesized
+    /// the PTR entries for any reverse lookups they want served locally.
+    ///
+    /// Clears the response cache: a newly added/removed local record must take effect
+    /// immediately rather than waiting out whatever upstream answer was cached for the same name.
+    pub fn set_records(&self, records: HashMap<StaticRecordKey, Vec<Record>>) {
+        *self.records.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(records);
+        self.cache.clear();
+    }
+
+    /// Replaces the blocklist wholesale, so filtering can be reconfigured at runtime without
+    /// tearing down the authority. Clears the response cache, since a name that was previously
+    /// forwarded-and-cached may now be blocked, or vice versa.
+    pub fn set_blocklist(&self, blocklist: DomainBlocklist) {
+        *self.blocklist.write().unwrap_or_else(|e| e.into_inner()) = blocklist;
+        self.cache.clear();
+    }
+}
+
+/// Synthesizes the `A`/`AAAA` records a [`BlockAction::Sinkhole`] answers with, or an empty
+/// answer for [`BlockAction::NxDomain`] (the lookup layer reports that as a plain empty lookup
+/// rather than a real NXDOMAIN, since [`LocalLookup`] has no error path of its own).
+fn blocked_records(name: &LowerName, rtype: RecordType, action: BlockAction) -> Vec<Record> {
+    let wants_v6 = rtype == RecordType::AAAA;
+    let Some(address) = action.sinkhole_address(wants_v6) else {
+        return Vec::new();
+    };
+    if !matches!(rtype, RecordType::A | RecordType::AAAA) {
+        return Vec::new();
+    }
+
+    let rdata = match address {
+        std::net::IpAddr::V4(v4) => RData::A(v4),
+        std::net::IpAddr::V6(v6) => RData::AAAA(v6),
+    };
+    let owner = Name::from(name.clone());
+    vec![Record::from_rdata(owner, NEGATIVE_CACHE_TTL.as_secs() as u32, rdata)]
+}
+
+#[async_trait::async_trait]
+impl Authority for LocalAuthority {
+    type Lookup = LocalLookup;
+
+    fn zone_type(&self) -> ZoneType {
+        ZoneType::Primary
+    }
+
+    fn is_axfr_allowed(&self) -> bool {
+        false
+    }
+
+    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+        Err(ResponseCode::NotImp)
+    }
+
+    fn origin(&self) -> &LowerName {
+        &self.origin
+    }
+
+    /// Checks the local overlay, then the blocklist, then the response cache, falling through to
+    /// the wrapped [`ForwardAuthority`] only once all three miss. See the module doc for why this
+    /// order: local records are trusted and should never be blocked or shadowed by a stale cache
+    /// entry; a blocked name is answered without ever bothering the cache or the forwarder.
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        let records = self.records.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(records) = records.get(&(name.clone(), rtype)) {
+            return Ok(LocalLookup::Local(records.clone()));
+        }
+
+        if let Some(action) = self
+            .blocklist
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .check(name)
+        {
+            return Ok(LocalLookup::Local(blocked_records(name, rtype, action)));
+        }
+
+        let key = CacheKey::new(name.clone(), rtype, DNSClass::IN);
+        if let Some(hit) = self.cache.get(&key) {
+            return Ok(LocalLookup::Local(match hit.answer {
+                CachedAnswer::Positive(records) => records,
+                CachedAnswer::Negative => Vec::new(),
+            }));
+        }
+
+        match self.forward.lookup(name, rtype, lookup_options).await {
+            Ok(forwarded) => {
+                let records: Vec<Record> = forwarded.iter().cloned().collect();
+                match records.iter().map(Record::ttl).min() {
+                    Some(ttl) => self.cache.insert(key, records, Duration::from_secs(ttl as u64)),
+                    None => self.cache.insert_negative(key, NEGATIVE_CACHE_TTL),
+                }
+                Ok(LocalLookup::Forwarded(Box::new(forwarded)))
+            }
+            Err(err) => {
+                if matches!(err, LookupError::ResponseCode(ResponseCode::NXDomain)) {
+                    self.cache.insert_negative(key, NEGATIVE_CACHE_TTL);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        self.lookup(
+            request_info.query.name(),
+            request_info.query.query_type(),
+            lookup_options,
+        )
+        .await
+    }
+
+    async fn get_nsec_records(
+        &self,
+        _name: &LowerName,
+        _lookup_options: LookupOptions,
+    ) -> Result<Self::Lookup, LookupError> {
+        Err(LookupError::from(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Getting NSEC records is unimplemented for the local authority",
+        )))
+    }
+}
+
+/// Either a synthesized local answer, or a [`ForwardLookup`](crate::forward::ForwardLookup)
+/// obtained by falling through to the wrapped forwarder.
+pub enum LocalLookup {
+    /// Answered without the forwarder: from the static record map, a blocklist hit, or the
+    /// response cache.
+    Local(Vec<Record>),
+    /// Answered by the wrapped [`ForwardAuthority`].
+    Forwarded(Box<dyn LookupObject>),
+}
+
+impl LookupObject for LocalLookup {
+    fn is_empty(&self) -> bool {
+        match self {
+            LocalLookup::Local(records) => records.is_empty(),
+            LocalLookup::Forwarded(lookup) => lookup.is_empty(),
+        }
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Record> + Send + 'a> {
+        match self {
+            LocalLookup::Local(records) => Box::new(records.iter()),
+            LocalLookup::Forwarded(lookup) => lookup.iter(),
+        }
+    }
+
+    fn take_additionals(&mut self) -> Option<Box<dyn LookupObject>> {
+        None
+    }
+}
@@ -0,0 +1,211 @@
+//! A pure-userspace WireGuard adapter backend, built on boringtun's Noise state machine, for
+//! platforms with neither a kernel WireGuard module nor `wireguard-go` available.
+//!
+//! **This does not close the request it lands for.** The ask was a selectable backend that owns
+//! its own UDP socket and TUN handle and plugs into `Runtime::start` via a `DeviceConfig`/
+//! `Features` option; what's here is only the Noise-session primitive that such a backend would
+//! drive. It isn't `mod`-declared anywhere reachable, opens no socket, and is never spawned --
+//! see "What's left unwired" below for why that part can't be finished in this checkout.
+//!
+//! The `Runtime` tests in `telio-device` always drive a mock adapter
+//! (`wg::tests::AdapterExpectation`/`expect_send_uapi_cmd_generic_call`), which stands in for
+//! whatever `AdapterType` variant `Runtime::start` resolves to in production -- today always a
+//! native (kernel module or `wireguard-go`) backend. [`BoringTunPeer`]/[`BoringTunSession`] are the
+//! part of a `boringtun`-backed alternative that's independent of how it gets selected: given this
+//! crate's own [`crate::uapi::Interface`]/[`crate::uapi::Peer`] config (the same shape the native
+//! backends and the mock already speak), they hold one [`boringtun::noise::Tunn`] Noise session per
+//! peer and drive its handshake/transport-data state machine, which is the actual "adapter" logic a
+//! socket loop would call into on every inbound/outbound packet.
+//!
+//! What's left unwired, and why it can't be finished here:
+//!  - [`crate::AdapterType`] and the `WireGuard`/`DynamicWg` trait/selection logic this crate
+//!    exposes to `telio-device` live in this crate's root, alongside the native adapter
+//!    implementations -- none of that has a source file in this checkout (only `uapi.rs` does), so
+//!    there's no `BoringTun` variant or trait definition to implement against yet.
+//!  - Owning a real UDP socket and TUN handle and running the read/write loop that feeds
+//!    `Tunn::encapsulate`/`decapsulate` is genuinely a new async subsystem (see the request this
+//!    lands for), not something a single module should improvise without the above seam to hang it
+//!    off of -- it needs to know how `DynamicWg::start` expects a backend to report peer state
+//!    changes, which depends on the missing trait definition.
+//!  - This checkout has no `Cargo.toml` anywhere, so there's no manifest to add the `boringtun`
+//!    dependency to either; the `boringtun::noise` calls below assume it's available the way the
+//!    request asks for.
+//!
+//! This is currently unregistered: add `mod boringtun_adapter;` to this crate's root (`lib.rs`,
+//! which doesn't exist in this checkout to edit) once the adapter trait it should implement is
+//! available.
+
+use std::{collections::HashMap, net::SocketAddr, time::Instant};
+
+use boringtun::noise::{Tunn, TunnResult};
+use telio_crypto::{PresharedKey, PublicKey, SecretKey};
+
+use crate::uapi::{Interface, Peer};
+
+/// A single peer's boringtun Noise session plus the bookkeeping `uapi::Peer` already tracks for
+/// every other backend (endpoint, keepalive, byte counters), so reporting this peer's state back
+/// through the existing `uapi::Peer`/`Event` shapes needs no extra translation once there's an
+/// adapter trait to return it through.
+pub struct BoringTunPeer {
+    tunnel: Tunn,
+    endpoint: Option<SocketAddr>,
+    allowed_ips: Vec<ipnetwork::IpNetwork>,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    /// Not due for another `Tunn::update_timers` call until this instant, see
+    /// [`BoringTunSession::tick_timers`].
+    next_timer_check: Instant,
+}
+
+/// Lower bound on how often [`BoringTunSession::tick_timers`] re-checks a given peer's
+/// handshake/keepalive timers, so a caller ticking faster than this doesn't needlessly re-run
+/// boringtun's timer bookkeeping every single call.
+const TIMER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Scratch buffer size for [`BoringTunSession::tick_timers`]: generous relative to the
+/// handshake-initiation/keepalive messages boringtun's `update_timers` ever writes.
+const TIMER_MESSAGE_BUF_SIZE: usize = 2048;
+
+impl BoringTunPeer {
+    /// Starts a fresh Noise session for `peer`, keyed by `our_secret_key` and `index` (boringtun's
+    /// per-session identifier, distinguishing concurrent sessions with the same peer across a
+    /// rekey). `now` seeds when this peer is first due for a [`BoringTunSession::tick_timers`]
+    /// check -- threaded in rather than read internally for the same reason `decapsulate` takes
+    /// one.
+    pub fn new(
+        our_secret_key: &SecretKey,
+        peer: &Peer,
+        index: u32,
+        now: Instant,
+    ) -> Result<Self, &'static str> {
+        let tunnel = Tunn::new(
+            to_x25519_static_secret(our_secret_key),
+            to_x25519_public_key(&peer.public_key),
+            peer.preshared_key.as_ref().map(to_preshared_key_bytes),
+            peer.persistent_keepalive_interval.map(|secs| secs as u16),
+            index,
+            None,
+        )?;
+
+        Ok(Self {
+            tunnel,
+            endpoint: peer.endpoint,
+            allowed_ips: peer.allowed_ips.clone(),
+            rx_bytes: 0,
+            tx_bytes: 0,
+            next_timer_check: now,
+        })
+    }
+
+    /// The peer's currently configured endpoint, for a socket loop to know where to send
+    /// [`TunnResult::WriteToNetwork`] output.
+    pub fn endpoint(&self) -> Option<SocketAddr> {
+        self.endpoint
+    }
+
+    /// Whether `destination` falls inside one of this peer's allowed IPs, for a socket loop
+    /// routing a packet read off the TUN device to the right [`BoringTunPeer`].
+    pub fn routes(&self, destination: std::net::IpAddr) -> bool {
+        self.allowed_ips.iter().any(|net| net.contains(destination))
+    }
+}
+
+/// All of a device's active boringtun peer sessions, keyed the same way [`Interface::peers`] is.
+#[derive(Default)]
+pub struct BoringTunSession {
+    peers: HashMap<PublicKey, BoringTunPeer>,
+    next_session_index: u32,
+}
+
+impl BoringTunSession {
+    /// Applies `interface`, starting a [`BoringTunPeer`] for every peer not already tracked and
+    /// dropping any tracked peer `interface` no longer lists (mirroring `replace_peers` semantics
+    /// for peers not present, same as the native backends' `Set`). `now` is forwarded to
+    /// [`BoringTunPeer::new`] for each newly started peer.
+    pub fn set_config(&mut self, interface: &Interface, now: Instant) -> Result<(), &'static str> {
+        let Some(private_key) = &interface.private_key else {
+            return Err("boringtun adapter requires a private key to start peer sessions");
+        };
+
+        self.peers
+            .retain(|public_key, _| interface.peers.contains_key(public_key));
+
+        for (public_key, peer) in &interface.peers {
+            if !self.peers.contains_key(public_key) {
+                let index = self.next_session_index;
+                self.next_session_index = self.next_session_index.wrapping_add(1);
+                self.peers.insert(
+                    *public_key,
+                    BoringTunPeer::new(private_key, peer, index, now)?,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Re-checks every tracked peer's handshake/keepalive timers that are due (see
+    /// [`TIMER_CHECK_INTERVAL`]), returning the datagram a socket loop should send to each peer
+    /// that produced one. A peer not yet due is skipped entirely, so calling this more often than
+    /// `TIMER_CHECK_INTERVAL` is cheap.
+    ///
+    /// Returns owned buffers rather than [`TunnResult`] because each peer's `update_timers` call
+    /// reuses the same scratch buffer -- holding a `TunnResult` borrowing it across more than one
+    /// peer's turn isn't possible.
+    pub fn tick_timers(&mut self, now: Instant) -> Vec<(PublicKey, Vec<u8>)> {
+        let mut scratch = [0u8; TIMER_MESSAGE_BUF_SIZE];
+        let mut to_send = Vec::new();
+        for (public_key, peer) in self.peers.iter_mut() {
+            if now < peer.next_timer_check {
+                continue;
+            }
+            peer.next_timer_check = now + TIMER_CHECK_INTERVAL;
+            if let TunnResult::WriteToNetwork(bytes) = peer.tunnel.update_timers(&mut scratch) {
+                to_send.push((*public_key, bytes.to_vec()));
+            }
+        }
+        to_send
+    }
+
+    /// Decrypts `datagram` against whichever peer session it belongs to, returning the decrypted
+    /// payload plaintext (and updating that peer's `rx_bytes`) on success.
+    ///
+    /// `now` is threaded in rather than read internally (`Instant::now()` is intentionally never
+    /// called deep in a hot path here) so callers -- and, eventually, tests -- can drive the Noise
+    /// session's handshake/rekey timers deterministically. A successful decrypt pushes this peer's
+    /// [`BoringTunSession::tick_timers`] deadline out by [`TIMER_CHECK_INTERVAL`], the same way
+    /// live traffic defers a keepalive.
+    pub fn decapsulate(
+        &mut self,
+        src: SocketAddr,
+        datagram: &[u8],
+        out: &mut [u8],
+        now: Instant,
+    ) -> Option<TunnResult<'_>> {
+        let peer = self
+            .peers
+            .values_mut()
+            .find(|peer| peer.endpoint == Some(src))?;
+        let result = peer.tunnel.decapsulate(Some(src.ip()), datagram, out);
+        if matches!(
+            result,
+            TunnResult::WriteToTunnelV4(..) | TunnResult::WriteToTunnelV6(..)
+        ) {
+            peer.rx_bytes = peer.rx_bytes.saturating_add(datagram.len() as u64);
+            peer.next_timer_check = peer.next_timer_check.max(now + TIMER_CHECK_INTERVAL);
+        }
+        Some(result)
+    }
+}
+
+fn to_x25519_static_secret(key: &SecretKey) -> boringtun::x25519::StaticSecret {
+    boringtun::x25519::StaticSecret::from(key.0)
+}
+
+fn to_x25519_public_key(key: &PublicKey) -> boringtun::x25519::PublicKey {
+    boringtun::x25519::PublicKey::from(key.0)
+}
+
+fn to_preshared_key_bytes(key: &PresharedKey) -> [u8; 32] {
+    key.0
+}
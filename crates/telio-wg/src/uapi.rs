@@ -2,7 +2,7 @@
 
 use ipnetwork::{IpNetwork, IpNetworkError};
 use serde::{Deserialize, Serialize};
-use telio_crypto::{KeyDecodeError, PublicKey, SecretKey};
+use telio_crypto::{KeyDecodeError, PresharedKey, PublicKey, SecretKey};
 use telio_model::mesh::{Node, NodeState};
 use telio_utils::telio_log_warn;
 use wireguard_uapi::{get, xplatform::set};
@@ -32,6 +32,10 @@ pub enum Error {
 pub struct Peer {
     /// Public key, the peer's primary identifier
     pub public_key: PublicKey,
+    /// Optional preshared key, layered on top of the Noise handshake for post-quantum hardening.
+    /// `None` means "no preshared key configured"; an explicit all-zero key clears a previously
+    /// set one (mirrors the UAPI convention for `preshared_key=`).
+    pub preshared_key: Option<PresharedKey>,
     /// Peer's endpoint with `IP address` and `UDP port` number
     pub endpoint: Option<SocketAddr>,
     /// Keep alive interval, `seconds` or `None`
@@ -44,6 +48,22 @@ pub struct Peer {
     pub tx_bytes: Option<u64>,
     /// Time since last handshakeor `None`, differs from WireGuard field meaning
     pub time_since_last_handshake: Option<Duration>,
+    /// How this peer entry should be applied on `Set`, allowing a caller to push a cheap
+    /// incremental mutation (a removal, or an allowed-IP delta) instead of always re-declaring
+    /// the full peer. Unused on `Get`.
+    pub op: PeerOp,
+}
+
+/// How a peer's UAPI `set` entry should be applied.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum PeerOp {
+    /// Declare the peer as given, creating or fully replacing it.
+    #[default]
+    Set,
+    /// Remove the peer; only `public_key` needs to be populated.
+    Remove,
+    /// Replace the peer's allowed IPs in place, leaving every other field untouched.
+    UpdateAllowedIps,
 }
 
 impl From<get::Peer> for Peer {
@@ -51,6 +71,7 @@ impl From<get::Peer> for Peer {
     fn from(item: get::Peer) -> Self {
         Self {
             public_key: PublicKey(item.public_key),
+            preshared_key: preshared_key_from_wire(item.preshared_key),
             endpoint: item.endpoint,
             persistent_keepalive_interval: Some(item.persistent_keepalive_interval.into()),
             allowed_ips: item
@@ -64,6 +85,7 @@ impl From<get::Peer> for Peer {
             time_since_last_handshake: Peer::calculate_time_since_last_handshake(Some(
                 item.last_handshake_time,
             )),
+            op: PeerOp::default(),
         }
     }
 }
@@ -73,6 +95,7 @@ impl From<set::Peer> for Peer {
     fn from(item: set::Peer) -> Self {
         Self {
             public_key: PublicKey(item.public_key),
+            preshared_key: item.preshared_key.and_then(preshared_key_from_wire),
             endpoint: item.endpoint,
             persistent_keepalive_interval: item.persistent_keepalive_interval.map(u32::from),
             allowed_ips: item
@@ -86,6 +109,15 @@ impl From<set::Peer> for Peer {
     }
 }
 
+/// Interprets a raw wire PSK, treating the all-zero key as "unset" per UAPI convention.
+fn preshared_key_from_wire(key: [u8; 32]) -> Option<PresharedKey> {
+    if key == [0u8; 32] {
+        None
+    } else {
+        Some(PresharedKey::new(key))
+    }
+}
+
 impl From<&Node> for Peer {
     fn from(other: &Node) -> Peer {
         Peer {
@@ -99,6 +131,8 @@ impl From<&Node> for Peer {
 }
 
 impl From<&Peer> for Node {
+    // Note: the preshared key is a WireGuard-layer secret and intentionally does not cross into
+    // the mesh-facing `Node` model.
     fn from(other: &Peer) -> Self {
         Self {
             public_key: other.public_key,
@@ -126,6 +160,7 @@ impl From<&Peer> for set::Peer {
     fn from(item: &Peer) -> Self {
         Self {
             public_key: item.public_key.0,
+            preshared_key: item.preshared_key.map(|key| key.into_bytes()),
             endpoint: item.endpoint,
             persistent_keepalive_interval: item.persistent_keepalive_interval.map(|x| x as u16),
             allowed_ips: item
@@ -136,6 +171,9 @@ impl From<&Peer> for set::Peer {
                     cidr_mask: ip.prefix(),
                 })
                 .collect(),
+            remove: item.op == PeerOp::Remove,
+            update_only: item.op == PeerOp::UpdateAllowedIps,
+            replace_allowed_ips: item.op == PeerOp::UpdateAllowedIps,
             ..Default::default()
         }
     }
@@ -152,6 +190,26 @@ pub struct Interface {
     pub fwmark: u32,
     /// Dictionary of Peer-s
     pub peers: BTreeMap<PublicKey, Peer>,
+    /// On `Set`, drop any configured peer not present in `peers` instead of leaving it in place.
+    /// Unused on `Get`.
+    pub replace_peers: bool,
+    /// Set while a [`Interface::rotate_private_key`] migration is in flight, `None` otherwise.
+    pub rotation: Option<RotationState>,
+}
+
+/// Tracks an in-flight make-before-break private-key rotation, see
+/// [`Interface::rotate_private_key`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationState {
+    /// The key being retired.
+    pub previous_key: SecretKey,
+    /// The key the interface has switched to.
+    pub new_key: SecretKey,
+    /// When the rotation was started.
+    pub started: Instant,
+    /// Handshake age recorded for each peer at rotation start, so a later reset can be detected
+    /// as that peer having completed a fresh handshake under `new_key`.
+    baseline: BTreeMap<PublicKey, Option<Duration>>,
 }
 
 impl From<get::Device> for Interface {
@@ -166,6 +224,8 @@ impl From<get::Device> for Interface {
                 .into_iter()
                 .map(|p| (PublicKey(p.public_key), Peer::from(p)))
                 .collect(),
+            replace_peers: false,
+            rotation: None,
         }
     }
 }
@@ -182,6 +242,8 @@ impl From<set::Device> for Interface {
                 .into_iter()
                 .map(|p| (PublicKey(p.public_key), Peer::from(p)))
                 .collect(),
+            replace_peers: item.replace_peers,
+            rotation: None,
         }
     }
 }
@@ -196,12 +258,66 @@ impl From<Interface> for set::Device {
                 0 => None,
                 x => Some(x),
             },
+            replace_peers: item.replace_peers,
             peers: item.peers.values().map(Into::<set::Peer>::into).collect(),
             ..Default::default()
         }
     }
 }
 
+impl Interface {
+    /// Stages a make-before-break rotation to `new`: the interface switches to `new` immediately
+    /// (so new handshakes authenticate under it), while `previous_key` is kept on record until
+    /// every peer that was configured at rotation start has completed a fresh handshake, detected
+    /// the same way as [`Peer::liveness`] tells a rekey apart from stale state: the peer's
+    /// `time_since_last_handshake` resetting toward zero.
+    ///
+    /// Calling this again while a rotation is already in flight replaces it, using the
+    /// still-current `private_key` as the new rotation's `previous_key`.
+    pub fn rotate_private_key(&mut self, new: SecretKey) -> &RotationState {
+        let previous_key = self.private_key.clone().unwrap_or_else(|| new.clone());
+        let baseline = self
+            .peers
+            .iter()
+            .map(|(public_key, peer)| (*public_key, peer.time_since_last_handshake))
+            .collect();
+
+        self.private_key = Some(new.clone());
+        self.rotation = Some(RotationState {
+            previous_key,
+            new_key: new,
+            started: Instant::now(),
+            baseline,
+        });
+
+        self.rotation.as_ref().unwrap_or_else(|| unreachable!())
+    }
+
+    /// Reports whether every peer present when [`Interface::rotate_private_key`] was called has
+    /// since completed a fresh handshake, i.e. migrated to the new key. Returns `true` if no
+    /// rotation is in progress.
+    pub fn rotation_complete(&self) -> bool {
+        let rotation = match &self.rotation {
+            Some(rotation) => rotation,
+            None => return true,
+        };
+
+        rotation.baseline.iter().all(|(public_key, baseline)| {
+            self.peers
+                .get(public_key)
+                .map_or(true, |peer| handshake_reset(*baseline, peer.time_since_last_handshake))
+        })
+    }
+
+    /// Retires the previous key once [`Interface::rotation_complete`] reports `true`. No-op if no
+    /// rotation is in progress or it hasn't finished yet.
+    pub fn finish_rotation(&mut self) {
+        if self.rotation_complete() {
+            self.rotation = None;
+        }
+    }
+}
+
 /// Types of commands
 #[derive(Debug, PartialEq)]
 pub enum Cmd {
@@ -223,6 +339,25 @@ pub struct Response {
 /// The connection state of the Node
 pub type PeerState = NodeState;
 
+/// A finer-grained, timer-derived liveness classification for a peer than plain [`PeerState`],
+/// see [`Peer::liveness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerLiveness {
+    /// No handshake has completed yet.
+    Connecting,
+    /// Session keys are fresh (within `REKEY_AFTER_TIME`), or older than that but still inside
+    /// `REJECT_AFTER_TIME` with no traffic since the previous snapshot.
+    Connected,
+    /// Session keys are aging past `REKEY_AFTER_TIME` but still valid, with traffic flowing; a
+    /// background rekey is expected soon.
+    ConnectedRekeying,
+    /// Past `REJECT_AFTER_TIME`, but still inside the rekey-attempt window with recent outbound
+    /// traffic suggesting a rekey is in flight.
+    Reconnecting,
+    /// No usable session and no sign of an in-flight rekey.
+    Dead,
+}
+
 /// Peer information to transmit
 #[derive(Debug, PartialEq, Eq)]
 pub struct Event {
@@ -232,6 +367,44 @@ pub struct Event {
     pub peer: Peer,
 }
 
+/// A classification of what changed between this snapshot and the previous one for the same
+/// peer, see [`Event::change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerChange {
+    /// The peer wasn't present in the previous snapshot at all.
+    New,
+    /// Nothing worth reporting changed.
+    Unchanged,
+    /// Only the peer's source endpoint changed, e.g. a mobile client roaming Wi-Fi <-> cellular
+    /// while keeping the same session alive. Callers can log NAT rebinding without treating this
+    /// as a full reconnect.
+    Roamed {
+        /// The previous endpoint, or `None` if this peer had none on record yet.
+        from: Option<SocketAddr>,
+        /// The new endpoint.
+        to: SocketAddr,
+    },
+}
+
+impl Event {
+    /// Classifies what changed between `self` and `prev`, the previous `get` snapshot of the same
+    /// peer, if any.
+    pub fn change(&self, prev: Option<&Peer>) -> PeerChange {
+        let prev = match prev {
+            Some(prev) => prev,
+            None => return PeerChange::New,
+        };
+
+        match self.peer.endpoint {
+            Some(to) if prev.endpoint != Some(to) => PeerChange::Roamed {
+                from: prev.endpoint,
+                to,
+            },
+            _ => PeerChange::Unchanged,
+        }
+    }
+}
+
 /// Analytics information to be conveyed
 #[derive(Clone, Debug)]
 pub struct AnalyticsEvent {
@@ -249,6 +422,103 @@ pub struct AnalyticsEvent {
     pub timestamp: Instant,
 }
 
+/// Instantaneous rate sample produced by [`PeerStats::ingest`] from two successive snapshots of
+/// the same peer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerRate {
+    /// Outbound bytes/sec since the previous snapshot.
+    pub tx_bytes_per_sec: f64,
+    /// Inbound bytes/sec since the previous snapshot.
+    pub rx_bytes_per_sec: f64,
+    /// Total number of handshakes observed for this peer so far.
+    pub handshake_count: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PeerStatsEntry {
+    tx_bytes: u64,
+    rx_bytes: u64,
+    timestamp: Instant,
+    last_handshake_age: Option<Duration>,
+    handshake_count: u64,
+}
+
+/// A stateful per-peer throughput and handshake-rate accumulator.
+///
+/// Consecutive UAPI `get` snapshots only carry cumulative `tx_bytes`/`rx_bytes` counters, which
+/// wrap at `u64` and push all rate math onto callers. `PeerStats` ingests each snapshot and turns
+/// it into an instantaneous byte-rate plus a running handshake count, so the analytics pipeline
+/// can report link utilization and rekey frequency per peer.
+#[derive(Debug, Default)]
+pub struct PeerStats {
+    peers: BTreeMap<PublicKey, PeerStatsEntry>,
+}
+
+impl PeerStats {
+    /// Creates an empty accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ingests a new snapshot of `peer`, taken at `timestamp`, returning the rate since the
+    /// previous snapshot seen for this public key, or `None` the first time a peer is observed.
+    pub fn ingest(&mut self, peer: &Peer, timestamp: Instant) -> Option<PeerRate> {
+        let tx_bytes = peer.tx_bytes.unwrap_or(0);
+        let rx_bytes = peer.rx_bytes.unwrap_or(0);
+
+        let rate = self.peers.get(&peer.public_key).map(|prev| {
+            let elapsed = timestamp
+                .saturating_duration_since(prev.timestamp)
+                .as_secs_f64();
+            let tx_delta = tx_bytes.wrapping_sub(prev.tx_bytes);
+            let rx_delta = rx_bytes.wrapping_sub(prev.rx_bytes);
+            let handshake_count = prev.handshake_count
+                + u64::from(handshake_reset(
+                    prev.last_handshake_age,
+                    peer.time_since_last_handshake,
+                ));
+
+            PeerRate {
+                tx_bytes_per_sec: if elapsed > 0.0 {
+                    tx_delta as f64 / elapsed
+                } else {
+                    0.0
+                },
+                rx_bytes_per_sec: if elapsed > 0.0 {
+                    rx_delta as f64 / elapsed
+                } else {
+                    0.0
+                },
+                handshake_count,
+            }
+        });
+
+        self.peers.insert(
+            peer.public_key,
+            PeerStatsEntry {
+                tx_bytes,
+                rx_bytes,
+                timestamp,
+                last_handshake_age: peer.time_since_last_handshake,
+                handshake_count: rate.map_or(0, |r| r.handshake_count),
+            },
+        );
+
+        rate
+    }
+}
+
+/// Whether a new handshake occurred between two successive handshake-age readings: the age resets
+/// toward zero instead of growing with the elapsed time, or this is the first handshake ever
+/// observed.
+fn handshake_reset(prev: Option<Duration>, current: Option<Duration>) -> bool {
+    match (prev, current) {
+        (Some(prev), Some(current)) => current < prev,
+        (None, Some(_)) => true,
+        _ => false,
+    }
+}
+
 impl Peer {
     /// Represents 2022-03-04 17:00:05
     #[cfg(test)]
@@ -314,6 +584,48 @@ impl Peer {
             == (&self.public_key, &other.endpoint, &other.allowed_ips)
     }
 
+    /// Classifies the peer's liveness from the WireGuard paper's timer constants, rather than the
+    /// single Connected/Connecting threshold used by [`Peer::state`].
+    ///
+    /// `prev` should be the previous `get` snapshot of this same peer, if any, so that byte-count
+    /// deltas can be used to tell an aging-but-idle session apart from one that's actively
+    /// rekeying. A clock that appears to have gone backwards (handled upstream by
+    /// [`Peer::calculate_time_since_last_handshake`] returning `None`) is treated the same as
+    /// "never handshaked", i.e. [`PeerLiveness::Connecting`].
+    pub fn liveness(&self, prev: Option<&Peer>) -> PeerLiveness {
+        // https://web.archive.org/web/20200603205723/https://www.wireguard.com/papers/wireguard.pdf
+        // 6.1 - 6.5
+        const REKEY_AFTER_TIME: Duration = Duration::from_secs(120);
+        const REJECT_AFTER_TIME: Duration = Duration::from_secs(180);
+        const REKEY_ATTEMPT_TIME: Duration = Duration::from_secs(90);
+
+        let h = match self.time_since_last_handshake {
+            Some(h) => h,
+            None => return PeerLiveness::Connecting,
+        };
+
+        let tx_increased = prev.map_or(false, |prev| {
+            self.tx_bytes.unwrap_or(0) > prev.tx_bytes.unwrap_or(0)
+        });
+        let rx_increased = prev.map_or(false, |prev| {
+            self.rx_bytes.unwrap_or(0) > prev.rx_bytes.unwrap_or(0)
+        });
+
+        if h < REKEY_AFTER_TIME {
+            PeerLiveness::Connected
+        } else if h < REJECT_AFTER_TIME {
+            if tx_increased || rx_increased {
+                PeerLiveness::ConnectedRekeying
+            } else {
+                PeerLiveness::Connected
+            }
+        } else if h < REJECT_AFTER_TIME + REKEY_ATTEMPT_TIME && tx_increased {
+            PeerLiveness::Reconnecting
+        } else {
+            PeerLiveness::Dead
+        }
+    }
+
     #[cfg(not(test))]
     fn get_unix_time() -> Result<Duration, SystemTimeError> {
         SystemTime::now().duration_since(UNIX_EPOCH)
@@ -504,6 +816,16 @@ fn parse_peer<R: Read>(
                         Error::ParsingError("endpoint", e.to_string())
                     })?)
                 }
+                "preshared_key" => {
+                    let key: PresharedKey = val.parse().map_err(|e: KeyDecodeError| {
+                        Error::ParsingError("preshared_key", e.to_string())
+                    })?;
+                    peer.preshared_key = if key.as_bytes() == &[0u8; 32] {
+                        None
+                    } else {
+                        Some(key)
+                    };
+                }
                 "persistent_keepalive_interval" => {
                     peer.persistent_keepalive_interval =
                         Some(val.parse().map_err(|e: ParseIntError| {
@@ -645,4 +967,365 @@ errno=0
         };
         assert_eq!(response_from_str(&resp_str), Ok(resp));
     }
+
+    #[test]
+    fn preshared_key_is_set() -> Result<(), Error> {
+        let pk1 = hex::encode(SecretKey::gen().public());
+        let psk = PresharedKey::new(SecretKey::gen().public().0);
+        let resp_str = format!(
+            "\
+public_key={pk1}
+preshared_key={}
+errno=0
+",
+            hex::encode(psk.as_bytes())
+        );
+
+        let resp = response_from_str(&resp_str)?;
+        let peer = resp
+            .interface
+            .expect("interface")
+            .peers
+            .into_values()
+            .next()
+            .expect("peer");
+        assert_eq!(peer.preshared_key, Some(psk));
+        Ok(())
+    }
+
+    #[test]
+    fn preshared_key_all_zero_is_cleared() -> Result<(), Error> {
+        let pk1 = hex::encode(SecretKey::gen().public());
+        let resp_str = format!(
+            "\
+public_key={pk1}
+preshared_key={}
+errno=0
+",
+            hex::encode([0u8; 32])
+        );
+
+        let resp = response_from_str(&resp_str)?;
+        let peer = resp
+            .interface
+            .expect("interface")
+            .peers
+            .into_values()
+            .next()
+            .expect("peer");
+        assert_eq!(peer.preshared_key, None);
+        Ok(())
+    }
+
+    #[test]
+    fn preshared_key_round_trips_through_set_peer() {
+        let psk = PresharedKey::new(SecretKey::gen().public().0);
+        let peer = Peer {
+            public_key: SecretKey::gen().public(),
+            preshared_key: Some(psk.clone()),
+            ..Peer::default()
+        };
+
+        let wire: set::Peer = (&peer).into();
+        assert_eq!(wire.preshared_key, Some(psk.into_bytes()));
+    }
+
+    #[test]
+    fn peer_op_remove_sets_remove_flag_only() {
+        let peer = Peer {
+            public_key: SecretKey::gen().public(),
+            op: PeerOp::Remove,
+            ..Peer::default()
+        };
+
+        let wire: set::Peer = (&peer).into();
+        assert!(wire.remove);
+        assert!(!wire.update_only);
+        assert!(!wire.replace_allowed_ips);
+    }
+
+    #[test]
+    fn peer_op_update_allowed_ips_sets_update_only_and_replace_flags() {
+        let peer = Peer {
+            public_key: SecretKey::gen().public(),
+            op: PeerOp::UpdateAllowedIps,
+            ..Peer::default()
+        };
+
+        let wire: set::Peer = (&peer).into();
+        assert!(!wire.remove);
+        assert!(wire.update_only);
+        assert!(wire.replace_allowed_ips);
+    }
+
+    #[test]
+    fn interface_replace_peers_carries_through_to_set_device() {
+        let interface = Interface {
+            replace_peers: true,
+            ..Interface::default()
+        };
+
+        let wire: set::Device = interface.into();
+        assert!(wire.replace_peers);
+    }
+
+    fn peer_with_handshake_age(age: Duration, tx_bytes: u64, rx_bytes: u64) -> Peer {
+        Peer {
+            public_key: SecretKey::gen().public(),
+            time_since_last_handshake: Some(age),
+            tx_bytes: Some(tx_bytes),
+            rx_bytes: Some(rx_bytes),
+            ..Peer::default()
+        }
+    }
+
+    #[test]
+    fn liveness_without_handshake_is_connecting() {
+        let peer = peer_with_handshake_age(Duration::from_secs(0), 0, 0);
+        let peer = Peer {
+            time_since_last_handshake: None,
+            ..peer
+        };
+        assert_eq!(peer.liveness(None), PeerLiveness::Connecting);
+    }
+
+    #[test]
+    fn liveness_fresh_handshake_is_connected() {
+        let peer = peer_with_handshake_age(Duration::from_secs(5), 100, 100);
+        assert_eq!(peer.liveness(None), PeerLiveness::Connected);
+    }
+
+    #[test]
+    fn liveness_aging_with_traffic_is_rekeying() {
+        let prev = peer_with_handshake_age(Duration::from_secs(130), 100, 100);
+        let peer = peer_with_handshake_age(Duration::from_secs(130), 200, 150);
+        assert_eq!(peer.liveness(Some(&prev)), PeerLiveness::ConnectedRekeying);
+    }
+
+    #[test]
+    fn liveness_aging_without_traffic_is_connected() {
+        let prev = peer_with_handshake_age(Duration::from_secs(130), 100, 100);
+        let peer = peer_with_handshake_age(Duration::from_secs(130), 100, 100);
+        assert_eq!(peer.liveness(Some(&prev)), PeerLiveness::Connected);
+    }
+
+    #[test]
+    fn liveness_past_reject_after_with_recent_tx_is_reconnecting() {
+        let prev = peer_with_handshake_age(Duration::from_secs(190), 100, 100);
+        let peer = peer_with_handshake_age(Duration::from_secs(190), 150, 100);
+        assert_eq!(peer.liveness(Some(&prev)), PeerLiveness::Reconnecting);
+    }
+
+    #[test]
+    fn liveness_past_reject_after_without_tx_is_dead() {
+        let prev = peer_with_handshake_age(Duration::from_secs(190), 100, 100);
+        let peer = peer_with_handshake_age(Duration::from_secs(190), 100, 150);
+        assert_eq!(peer.liveness(Some(&prev)), PeerLiveness::Dead);
+    }
+
+    #[test]
+    fn liveness_past_rekey_attempt_window_is_dead() {
+        let prev = peer_with_handshake_age(Duration::from_secs(300), 100, 100);
+        let peer = peer_with_handshake_age(Duration::from_secs(300), 200, 100);
+        assert_eq!(peer.liveness(Some(&prev)), PeerLiveness::Dead);
+    }
+
+    #[test]
+    fn change_with_no_previous_snapshot_is_new() {
+        let event = Event {
+            state: PeerState::Connected,
+            peer: peer_with_handshake_age(Duration::from_secs(5), 0, 0),
+        };
+        assert_eq!(event.change(None), PeerChange::New);
+    }
+
+    #[test]
+    fn change_with_same_endpoint_is_unchanged() {
+        let addr: SocketAddr = "10.0.0.1:51820".parse().unwrap();
+        let mut prev = peer_with_handshake_age(Duration::from_secs(5), 100, 100);
+        prev.endpoint = Some(addr);
+        let mut peer = peer_with_handshake_age(Duration::from_secs(5), 150, 150);
+        peer.endpoint = Some(addr);
+
+        let event = Event {
+            state: PeerState::Connected,
+            peer,
+        };
+        assert_eq!(event.change(Some(&prev)), PeerChange::Unchanged);
+    }
+
+    #[test]
+    fn change_with_new_endpoint_while_fresh_is_roamed() {
+        let from: SocketAddr = "10.0.0.1:51820".parse().unwrap();
+        let to: SocketAddr = "10.0.0.2:51820".parse().unwrap();
+        let mut prev = peer_with_handshake_age(Duration::from_secs(5), 100, 100);
+        prev.endpoint = Some(from);
+        // Handshake age stays fresh; only the endpoint rebinds, as with a Wi-Fi/cellular switch.
+        let mut peer = peer_with_handshake_age(Duration::from_secs(5), 150, 150);
+        peer.endpoint = Some(to);
+
+        let event = Event {
+            state: PeerState::Connected,
+            peer,
+        };
+        assert_eq!(
+            event.change(Some(&prev)),
+            PeerChange::Roamed {
+                from: Some(from),
+                to
+            }
+        );
+    }
+
+    #[test]
+    fn peer_stats_first_snapshot_has_no_rate() {
+        let mut stats = PeerStats::new();
+        let peer = peer_with_handshake_age(Duration::from_secs(5), 1000, 2000);
+        assert_eq!(stats.ingest(&peer, Instant::now()), None);
+    }
+
+    #[test]
+    fn peer_stats_computes_byte_rate_over_elapsed_time() {
+        let mut stats = PeerStats::new();
+        let public_key = SecretKey::gen().public();
+        let t0 = Instant::now();
+
+        let first = Peer {
+            public_key,
+            tx_bytes: Some(1_000),
+            rx_bytes: Some(2_000),
+            time_since_last_handshake: Some(Duration::from_secs(5)),
+            ..Peer::default()
+        };
+        assert_eq!(stats.ingest(&first, t0), None);
+
+        let second = Peer {
+            public_key,
+            tx_bytes: Some(2_000),
+            rx_bytes: Some(4_000),
+            time_since_last_handshake: Some(Duration::from_secs(10)),
+            ..Peer::default()
+        };
+        let rate = stats
+            .ingest(&second, t0 + Duration::from_secs(2))
+            .expect("rate");
+        assert_eq!(rate.tx_bytes_per_sec, 500.0);
+        assert_eq!(rate.rx_bytes_per_sec, 1_000.0);
+        assert_eq!(rate.handshake_count, 0);
+    }
+
+    #[test]
+    fn peer_stats_counts_handshake_resets() {
+        let mut stats = PeerStats::new();
+        let public_key = SecretKey::gen().public();
+        let t0 = Instant::now();
+
+        let first = Peer {
+            public_key,
+            tx_bytes: Some(100),
+            rx_bytes: Some(100),
+            time_since_last_handshake: Some(Duration::from_secs(100)),
+            ..Peer::default()
+        };
+        assert_eq!(stats.ingest(&first, t0), None);
+
+        // Handshake age drops toward zero: a fresh handshake completed.
+        let second = Peer {
+            public_key,
+            tx_bytes: Some(200),
+            rx_bytes: Some(200),
+            time_since_last_handshake: Some(Duration::from_secs(1)),
+            ..Peer::default()
+        };
+        let rate = stats
+            .ingest(&second, t0 + Duration::from_secs(1))
+            .expect("rate");
+        assert_eq!(rate.handshake_count, 1);
+
+        // Age keeps growing normally: no new handshake.
+        let third = Peer {
+            public_key,
+            tx_bytes: Some(300),
+            rx_bytes: Some(300),
+            time_since_last_handshake: Some(Duration::from_secs(2)),
+            ..Peer::default()
+        };
+        let rate = stats
+            .ingest(&third, t0 + Duration::from_secs(2))
+            .expect("rate");
+        assert_eq!(rate.handshake_count, 1);
+    }
+
+    #[test]
+    fn peer_stats_handles_counter_wraparound() {
+        let mut stats = PeerStats::new();
+        let public_key = SecretKey::gen().public();
+        let t0 = Instant::now();
+
+        let first = Peer {
+            public_key,
+            tx_bytes: Some(u64::MAX - 100),
+            rx_bytes: Some(0),
+            ..Peer::default()
+        };
+        assert_eq!(stats.ingest(&first, t0), None);
+
+        let second = Peer {
+            public_key,
+            tx_bytes: Some(50),
+            rx_bytes: Some(0),
+            ..Peer::default()
+        };
+        let rate = stats
+            .ingest(&second, t0 + Duration::from_secs(1))
+            .expect("rate");
+        assert_eq!(rate.tx_bytes_per_sec, 151.0);
+    }
+
+    #[test]
+    fn rotate_private_key_switches_immediately_and_stages_rotation() {
+        let old_key = SecretKey::gen();
+        let new_key = SecretKey::gen();
+        let mut interface = Interface {
+            private_key: Some(old_key.clone()),
+            ..Interface::default()
+        };
+
+        interface.rotate_private_key(new_key.clone());
+
+        assert_eq!(interface.private_key, Some(new_key.clone()));
+        let rotation = interface.rotation.as_ref().expect("rotation");
+        assert_eq!(rotation.previous_key, old_key);
+        assert_eq!(rotation.new_key, new_key);
+    }
+
+    #[test]
+    fn rotation_is_incomplete_until_every_peer_rehandshakes() {
+        let mut interface = Interface::default();
+        let peer_a = peer_with_handshake_age(Duration::from_secs(50), 0, 0);
+        let peer_b = peer_with_handshake_age(Duration::from_secs(50), 0, 0);
+        interface.peers.insert(peer_a.public_key, peer_a.clone());
+        interface.peers.insert(peer_b.public_key, peer_b.clone());
+
+        interface.rotate_private_key(SecretKey::gen());
+        assert!(!interface.rotation_complete());
+
+        // Only peer_a completes a fresh handshake under the new key.
+        let peer_a_migrated = Peer {
+            time_since_last_handshake: Some(Duration::from_secs(1)),
+            ..peer_a.clone()
+        };
+        interface.peers.insert(peer_a_migrated.public_key, peer_a_migrated);
+        assert!(!interface.rotation_complete());
+
+        let peer_b_migrated = Peer {
+            time_since_last_handshake: Some(Duration::from_secs(1)),
+            ..peer_b.clone()
+        };
+        interface.peers.insert(peer_b_migrated.public_key, peer_b_migrated);
+        assert!(interface.rotation_complete());
+
+        interface.finish_rotation();
+        assert!(interface.rotation.is_none());
+    }
 }
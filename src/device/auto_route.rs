@@ -0,0 +1,279 @@
+//! Opt-in automatic OS routing-table management for the tunnel.
+//!
+//! When enabled via [`AutoRouteConfig`], [`AutoRouteManager`] installs the tunnel's routes into a
+//! dedicated routing table rather than leaving route installation entirely to the integrator: a
+//! default route (`0.0.0.0/0` and `::/0`) while an exit node is connected, or one route per
+//! meshnet peer address in meshnet-only mode. An `ip rule` sends matching traffic into that table,
+//! and a second, higher-priority rule keeps libtelio's own encapsulated WireGuard sockets (marked
+//! with `fwmark`) on the main table, so their packets aren't re-captured by the rule that was just
+//! installed, which would otherwise create a routing loop.
+//!
+//! Only implemented for Linux, where `ip-route(8)`/`ip-rule(8)` are available; every other
+//! platform gets a no-op backend so callers don't need to `cfg`-gate their own code.
+
+use std::net::IpAddr;
+
+use telio_utils::telio_log_debug;
+
+/// Configuration for the opt-in automatic routing subsystem, set via [`DeviceConfig::auto_route`](super::DeviceConfig::auto_route).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoRouteConfig {
+    /// Dedicated routing table id the tunnel's routes are installed into.
+    pub table: u32,
+    /// Priority of the `ip rule` that sends traffic into `table`.
+    pub rule_priority: u32,
+    /// fwmark libtelio's own encapsulated WireGuard sockets are marked with, so a
+    /// higher-priority `from all fwmark <mark> lookup main` rule keeps them out of `table`.
+    pub fwmark: u32,
+}
+
+impl Default for AutoRouteConfig {
+    fn default() -> Self {
+        Self {
+            table: 73110,
+            rule_priority: 100,
+            fwmark: 11673,
+        }
+    }
+}
+
+/// What [`AutoRouteManager::connect`] should route into the dedicated table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteTarget {
+    /// Route everything -- used while an exit node is connected.
+    Default,
+    /// Route only these addresses -- used in meshnet-only mode, one entry per peer.
+    Addresses(Vec<IpAddr>),
+}
+
+/// Errors from shelling out to the platform's routing tools.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The routing tool couldn't even be started.
+    #[error("Failed to run '{0}': {1}")]
+    Spawn(&'static str, std::io::Error),
+    /// The routing tool ran, but reported failure.
+    #[error("'{0}' exited with {1}")]
+    NonZeroExit(&'static str, std::process::ExitStatus),
+    /// `connect` was called without a tunnel interface name to route through, see
+    /// [`AutoRouteManager::new`].
+    #[error("auto_route requires a tunnel interface name, but none was configured")]
+    MissingInterface,
+}
+
+/// Installs and tears down the routes/rules described by [`AutoRouteConfig`].
+pub struct AutoRouteManager {
+    config: AutoRouteConfig,
+    /// The tunnel interface every installed route is bound to via `dev <tun_name>`, so the
+    /// kernel has a nexthop to resolve the route through. `None` if the tunnel's name wasn't
+    /// known when the manager was created (e.g. the adapter was handed a raw fd instead of a
+    /// name); [`AutoRouteManager::connect`] then fails rather than installing an unresolvable
+    /// route.
+    tun_name: Option<String>,
+    installed: bool,
+}
+
+impl AutoRouteManager {
+    /// Creates a manager for `config`, routing through `tun_name` -- the same interface name
+    /// passed to [`DeviceConfig::name`](super::DeviceConfig::name). Nothing is installed until
+    /// [`AutoRouteManager::connect`] is called.
+    pub fn new(config: AutoRouteConfig, tun_name: Option<String>) -> Self {
+        Self {
+            config,
+            tun_name,
+            installed: false,
+        }
+    }
+
+    /// Installs the routes/rules described by `target`, first tearing down whatever was
+    /// previously installed (if anything), so reconfiguring never leaves stale routes behind.
+    pub fn connect(&mut self, target: &RouteTarget) -> Result<(), Error> {
+        let tun_name = self.tun_name.as_deref().ok_or(Error::MissingInterface)?;
+        self.disconnect()?;
+        telio_log_debug!("auto_route: installing {:?} into table {}", target, self.config.table);
+        backend::install(&self.config, tun_name, target)?;
+        self.installed = true;
+        Ok(())
+    }
+
+    /// Reverses every change made by [`AutoRouteManager::connect`]. No-op if nothing is
+    /// currently installed.
+    pub fn disconnect(&mut self) -> Result<(), Error> {
+        if self.installed {
+            telio_log_debug!("auto_route: tearing down table {}", self.config.table);
+            backend::teardown(&self.config)?;
+            self.installed = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AutoRouteManager {
+    fn drop(&mut self) {
+        let _ = self.disconnect();
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use std::process::Command;
+
+    use super::{AutoRouteConfig, Error, RouteTarget};
+
+    pub(super) fn install(
+        config: &AutoRouteConfig,
+        tun_name: &str,
+        target: &RouteTarget,
+    ) -> Result<(), Error> {
+        match target {
+            RouteTarget::Default => {
+                run_owned(&route_add_argv(tun_name, config.table, "default", false))?;
+                run_owned(&route_add_argv(tun_name, config.table, "default", true))?;
+            }
+            RouteTarget::Addresses(addresses) => {
+                for address in addresses {
+                    run_owned(&route_add_argv(
+                        tun_name,
+                        config.table,
+                        &address.to_string(),
+                        address.is_ipv6(),
+                    ))?;
+                }
+            }
+        }
+
+        run(&[
+            "rule",
+            "add",
+            "priority",
+            &config.rule_priority.to_string(),
+            "table",
+            &config.table.to_string(),
+        ])?;
+        // Keep libtelio's own encapsulated sockets out of the dedicated table: a higher-priority
+        // rule sends fwmark'ed traffic straight back to the main table, avoiding a routing loop.
+        run(&[
+            "rule",
+            "add",
+            "priority",
+            &config.rule_priority.saturating_sub(1).to_string(),
+            "fwmark",
+            &config.fwmark.to_string(),
+            "lookup",
+            "main",
+        ])?;
+
+        Ok(())
+    }
+
+    pub(super) fn teardown(config: &AutoRouteConfig) -> Result<(), Error> {
+        // Best-effort: the rules/routes might already be gone (e.g. interface torn down first),
+        // which isn't an error worth propagating from a teardown path.
+        let _ = run(&[
+            "rule",
+            "del",
+            "priority",
+            &config.rule_priority.to_string(),
+        ]);
+        let _ = run(&[
+            "rule",
+            "del",
+            "priority",
+            &config.rule_priority.saturating_sub(1).to_string(),
+        ]);
+        let _ = run(&["route", "flush", "table", &config.table.to_string()]);
+        let _ = run(&[
+            "-6",
+            "route",
+            "flush",
+            "table",
+            &config.table.to_string(),
+        ]);
+
+        Ok(())
+    }
+
+    /// Builds the argv for `ip [-6] route add <destination> dev <tun_name> table <table>`, pulled
+    /// out of [`install`] so it's testable without actually spawning `ip`.
+    fn route_add_argv(tun_name: &str, table: u32, destination: &str, ipv6: bool) -> Vec<String> {
+        let mut argv = Vec::with_capacity(7);
+        if ipv6 {
+            argv.push("-6".to_string());
+        }
+        argv.push("route".to_string());
+        argv.push("add".to_string());
+        argv.push(destination.to_string());
+        argv.push("dev".to_string());
+        argv.push(tun_name.to_string());
+        argv.push("table".to_string());
+        argv.push(table.to_string());
+        argv
+    }
+
+    fn run_owned(args: &[String]) -> Result<(), Error> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run(&args)
+    }
+
+    fn run(args: &[&str]) -> Result<(), Error> {
+        let status = Command::new("ip")
+            .args(args)
+            .status()
+            .map_err(|e| Error::Spawn("ip", e))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::NonZeroExit("ip", status))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::route_add_argv;
+
+        #[test]
+        fn default_route_binds_the_tunnel_device() {
+            let argv = route_add_argv("utun123", 73110, "default", false);
+            assert_eq!(
+                argv,
+                vec!["route", "add", "default", "dev", "utun123", "table", "73110"]
+            );
+        }
+
+        #[test]
+        fn ipv6_default_route_gets_the_dash_6_flag() {
+            let argv = route_add_argv("utun123", 73110, "default", true);
+            assert_eq!(
+                argv,
+                vec!["-6", "route", "add", "default", "dev", "utun123", "table", "73110"]
+            );
+        }
+
+        #[test]
+        fn per_address_route_binds_the_tunnel_device() {
+            let argv = route_add_argv("utun123", 73110, "10.5.0.2", false);
+            assert_eq!(
+                argv,
+                vec!["route", "add", "10.5.0.2", "dev", "utun123", "table", "73110"]
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod backend {
+    use super::{AutoRouteConfig, Error, RouteTarget};
+
+    pub(super) fn install(
+        _config: &AutoRouteConfig,
+        _tun_name: &str,
+        _target: &RouteTarget,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub(super) fn teardown(_config: &AutoRouteConfig) -> Result<(), Error> {
+        Ok(())
+    }
+}
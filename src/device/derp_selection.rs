@@ -0,0 +1,67 @@
+//! Weighted load-spreading selection of DERP servers.
+//!
+//! `log_nat()` and the DERP config both used to gravitate to the single lowest-`weight` server
+//! (`servers.iter().min_by_key(|s| s.weight)`), concentrating load on whichever relay the fleet
+//! operator ranked best. [`weighted_order`] instead produces a full ordering of the configured
+//! servers where a server's probability of landing near the front is proportional to its inverse
+//! weight (lower `weight` remains preferred, matching the old tie-break, but no longer
+//! deterministically wins every time) -- callers use the first entry as the primary connection and
+//! the rest as the failover list, same as today's `min_by_key` result plus whatever was left over.
+//!
+//! The ordering is seeded from the device's public key (and each server's identity), not re-rolled
+//! with fresh randomness, so it's stable across reconnects -- a node doesn't reshuffle servers
+//! every time `set_config()` runs, it just doesn't always pick the same one as every other node.
+
+use telio_crypto::PublicKey;
+use telio_model::config::Server as DerpServer;
+
+/// Orders `servers` by a per-device, per-server weighted-random key (Efraimidis-Spirakis weighted
+/// sampling without replacement, using `1 / weight` as the sampling weight so a lower `weight`
+/// server is more likely to sort first). Returns a new `Vec` in preference order; the input slice
+/// is left untouched.
+pub fn weighted_order(public_key: &PublicKey, servers: &[DerpServer]) -> Vec<DerpServer> {
+    let mut keyed: Vec<(f64, &DerpServer)> = servers
+        .iter()
+        .map(|server| (selection_key(public_key, server), server))
+        .collect();
+
+    // Descending: the largest key (per Efraimidis-Spirakis) is the "winner" for this round.
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    keyed.into_iter().map(|(_, server)| server.clone()).collect()
+}
+
+/// `u^weight` for a per-(device, server) uniform random `u` in `(0, 1)`, which is the
+/// Efraimidis-Spirakis key for sampling with probability proportional to `1 / weight`: a smaller
+/// `weight` shrinks the exponent, pushing the key closer to 1 (and so earlier in the descending
+/// sort) more often, without ever removing the randomness entirely.
+fn selection_key(public_key: &PublicKey, server: &DerpServer) -> f64 {
+    let u = uniform_unit(seed_for(public_key, server));
+    let weight = (server.weight.max(1)) as f64;
+    u.powf(weight)
+}
+
+/// A deterministic per-(device, server) seed: stable across reconnects (same inputs every time),
+/// but different across devices and across servers, which is all a selection tie-break needs --
+/// not cryptographic unpredictability, so a simple FNV-1a hash avoids depending on a keyed-hash or
+/// CSPRNG crate for this.
+fn seed_for(public_key: &PublicKey, server: &DerpServer) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut update = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    update(&public_key.0);
+    update(&server.ipv4.octets());
+    update(&server.stun_port.to_be_bytes());
+    update(&server.weight.to_be_bytes());
+    hash
+}
+
+/// Maps a 64-bit hash onto the open interval `(0, 1)`, which `x.powf(weight)` needs (`0` or `1`
+/// would collapse every server of the same weight to the same key).
+fn uniform_unit(seed: u64) -> f64 {
+    ((seed >> 11) as f64 + 1.0) / ((1u64 << 53) as f64 + 2.0)
+}
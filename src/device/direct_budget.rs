@@ -0,0 +1,97 @@
+//! A bounded budget on how many peers are kept upgraded to a direct connection.
+//!
+//! Nothing currently caps how many peers `DirectEntities`/`CrossPingCheck` will attempt to
+//! upgrade to direct, which costs battery and CPU on large meshnets where most peers are rarely
+//! active. [`DirectBudgetConfig`] bounds it, and [`plan`] decides -- given a ranking of candidate
+//! peers by recent traffic volume -- which peers should be promoted to direct, which should stay
+//! direct, and which should be demoted back to the DERP relayed path, keeping at most
+//! `max_direct_peers` assigned at any time and preferring to hold `ideal_direct_peers` steady
+//! state.
+//!
+//! Actually instructing `wg_controller`/`UpgradeSync` to perform a promotion or demotion is left
+//! as an integration seam, and genuinely so: `wg_controller` has no source file in this checkout
+//! to add a call to, and the only thing this tree ever does with `UpgradeSync` is construct one
+//! (`UpgradeSync::new` in `mod.rs`) and stop it on shutdown -- no other method on it is ever
+//! called, so there's no established
+//! calling convention to mirror the way there was for `Multiplexer::get_channel`. This module
+//! implements the ranking and assignment decision, which is the part that's independent of that
+//! API; `Runtime::run_direct_budget_maintenance` (in `mod.rs`) records the decision but does not
+//! enforce it.
+
+use telio_crypto::PublicKey;
+
+/// Configures how many peers may be upgraded to a direct connection at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectBudgetConfig {
+    /// Hard ceiling: a peer already direct keeps its slot up to this rank even if it's past
+    /// `ideal_direct_peers`, so a momentary dip in its traffic ranking doesn't immediately evict
+    /// it. Never more than this many peers are kept direct at once.
+    pub max_direct_peers: usize,
+    /// Soft target: a peer not already direct can only be newly promoted within this rank, so the
+    /// budget settles back toward this steady-state count over time rather than camping at
+    /// `max_direct_peers`.
+    pub ideal_direct_peers: usize,
+}
+
+impl Default for DirectBudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_direct_peers: 32,
+            ideal_direct_peers: 16,
+        }
+    }
+}
+
+/// Whether a peer should be on the direct path or the DERP relayed path after this round's
+/// maintenance decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assignment {
+    Direct,
+    Relayed,
+}
+
+/// A candidate peer's recent activity, used to rank it against the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerRank {
+    pub public_key: PublicKey,
+    /// Bytes sent + received recently; higher is more deserving of a direct connection.
+    pub recent_traffic_bytes: u64,
+    /// Was this peer already on the direct path going into this round?
+    pub currently_direct: bool,
+}
+
+/// Ranks `candidates` by recent traffic (most-active first) and decides the new assignment for
+/// every one of them under `config`'s budget.
+///
+/// Ties prefer keeping a peer that's already direct over promoting a new one, so the budget
+/// doesn't thrash two similarly-active peers back and forth every round. A peer already direct is
+/// allowed to stay direct up to `max_direct_peers`; a peer being newly promoted is only allowed up
+/// to the tighter `ideal_direct_peers`, so the two limits both do real work: `ideal_direct_peers`
+/// is where the budget settles in steady state, `max_direct_peers` is the slack that keeps an
+/// already-direct peer from being evicted the moment it dips just below that target.
+pub fn plan(config: &DirectBudgetConfig, candidates: &[PeerRank]) -> Vec<(PublicKey, Assignment)> {
+    let mut ranked: Vec<&PeerRank> = candidates.iter().collect();
+    ranked.sort_by(|a, b| {
+        b.recent_traffic_bytes
+            .cmp(&a.recent_traffic_bytes)
+            .then(b.currently_direct.cmp(&a.currently_direct))
+    });
+
+    ranked
+        .into_iter()
+        .enumerate()
+        .map(|(rank, peer)| {
+            let within_budget = if peer.currently_direct {
+                rank < config.max_direct_peers
+            } else {
+                rank < config.ideal_direct_peers
+            };
+            let assignment = if within_budget {
+                Assignment::Direct
+            } else {
+                Assignment::Relayed
+            };
+            (peer.public_key, assignment)
+        })
+        .collect()
+}
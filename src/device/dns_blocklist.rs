@@ -0,0 +1,37 @@
+//! Runtime-updatable configuration for magic DNS's domain blocklist/filtering feature.
+//!
+//! The actual interception -- matching a query name against the list and answering NXDOMAIN or a
+//! sink address instead of forwarding it -- belongs in the resolver itself (see
+//! `telio-dns`'s `blocklist` module, which implements the exact-suffix matching and compiled set),
+//! and exposing it needs a new `Features` flag alongside `exit_dns`; neither is available to wire
+//! up from this checkout (`Features` is defined in a crate this checkout doesn't include, and
+//! `telio-dns`'s crate root wasn't present to register a new module in). What's here is the part
+//! `Runtime` can own today: holding the current requested list/action so a
+//! `Device::set_dns_blocklist` call takes effect immediately, independent of that wiring.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// How a blocked query should be answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsBlockAction {
+    NxDomain,
+    Sinkhole { v4: Ipv4Addr, v6: Ipv6Addr },
+}
+
+/// The set of blocked domains/patterns currently requested, and how matches should be answered.
+/// Each entry blocks itself and every subdomain (exact-suffix matching).
+#[derive(Debug, Clone, Default)]
+pub struct DnsBlocklistConfig {
+    pub domains: Vec<String>,
+    pub action: Option<DnsBlockAction>,
+}
+
+impl DnsBlocklistConfig {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.action.is_some() && !self.domains.is_empty()
+    }
+}
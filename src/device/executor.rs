@@ -0,0 +1,37 @@
+//! A pluggable task-spawning abstraction for the runtime.
+//!
+//! `Runtime` (and helpers like `log_nat`'s background NAT probe and the `_panic` test hook) used
+//! to hard-code `tokio::spawn`, which assumes the embedder both runs a Tokio multi-thread runtime
+//! and is fine with libtelio spawning its own background tasks onto it. That's awkward for a host
+//! that already owns its reactor (e.g. a single-threaded executor, or an app that wants every
+//! libtelio task accounted for on its own scheduler). [`Executor`] is the seam: anything the
+//! runtime previously fired with `tokio::spawn` now goes through `Entities::spawn`, which forwards
+//! to whichever [`Executor`] was supplied in [`super::DeviceConfig::executor`] (an `Arc<dyn
+//! Executor>`, so it can be shared with the embedder). [`TokioExecutor`] is the default, plugged in
+//! by `Runtime::start` whenever the config doesn't set one, so existing behavior is unchanged for
+//! every caller that doesn't opt in.
+
+use std::{future::Future, pin::Pin};
+
+/// A boxed, type-erased future suitable for fire-and-forget spawning.
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Something that can run a [`BoxFuture`] to completion without blocking the caller.
+///
+/// Implementors must be safe to invoke from any task and to hold onto for the runtime's entire
+/// lifetime, hence `Send + Sync`.
+pub trait Executor: Send + Sync {
+    /// Schedules `future` to run, returning immediately.
+    fn spawn(&self, future: BoxFuture);
+}
+
+/// The default [`Executor`]: spawns onto whichever Tokio runtime is current, matching the
+/// `tokio::spawn` calls this replaces.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn(&self, future: BoxFuture) {
+        tokio::spawn(future);
+    }
+}
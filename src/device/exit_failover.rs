@@ -0,0 +1,247 @@
+//! Automatic failover across an ordered group of candidate exit nodes.
+//!
+//! `connect_exit_node`/`disconnect_exit_nodes` only ever track a single
+//! `RequestedState.exit_node`, so if that VPN/exit peer goes dead the tunnel just stalls until the
+//! app notices and manually reconnects. [`FailoverGroup`] (stored in `RequestedState` once a
+//! caller opts in via `Device::connect_exit_node_group`) tracks an ordered list of candidates and,
+//! for whichever one is currently active, how long it's gone without completing a handshake. Once
+//! that exceeds `FailoverConfig::handshake_timeout`, [`FailoverGroup::tick`] demotes it and hands
+//! back the next candidate that isn't currently serving a backoff penalty -- or, if every
+//! candidate is either exhausted or backed off, signals that the caller should fall back to the
+//! relayed path.
+//!
+//! A candidate that gets demoted is given an exponentially growing backoff before it's eligible to
+//! be promoted again, so a single flapping candidate doesn't get retried every tick forever; the
+//! backoff resets once the candidate is observed healthy again.
+//!
+//! This module only decides *which* candidate should be active; actually driving the switch
+//! through `connect_exit_node`/`consolidate_wg_state` and publishing the `Node` event describing it
+//! is `Runtime::run_exit_failover`, in `mod.rs`, which feeds this from `wg_event_subscriber`.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use telio_crypto::PublicKey;
+use telio_model::mesh::ExitNode;
+use telio_utils::exponential_backoff::ExponentialBackoffBounds;
+use telio_wg::uapi::PeerState;
+
+/// Configures automatic exit-node failover, see the module doc.
+#[derive(Debug, Clone, Copy)]
+pub struct FailoverConfig {
+    /// How long the active candidate may go without completing a handshake before it's demoted
+    /// in favor of the next one.
+    pub handshake_timeout: Duration,
+    /// Backoff applied to a candidate every time it's demoted, bounding how soon it becomes
+    /// eligible to be promoted again.
+    pub backoff: ExponentialBackoffBounds,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: Duration::from_secs(15),
+            backoff: ExponentialBackoffBounds {
+                initial: Duration::from_secs(5),
+                maximal: Some(Duration::from_secs(300)),
+            },
+        }
+    }
+}
+
+/// Per-candidate backoff bookkeeping.
+#[derive(Debug, Clone, Copy)]
+struct Backoff {
+    /// The penalty to apply the next time this candidate is demoted.
+    next: Duration,
+    /// Not eligible for promotion again until this instant.
+    skip_until: Instant,
+}
+
+/// What [`FailoverGroup::tick`] decided should happen this round.
+#[derive(Debug, Clone)]
+pub enum FailoverDecision {
+    /// The active candidate is healthy (or hasn't been unhealthy long enough yet); keep it.
+    Stay,
+    /// The active candidate has been unhealthy for too long; switch to this one instead.
+    Switch(ExitNode),
+    /// The active candidate went unhealthy and every other candidate is currently serving a
+    /// backoff penalty; fall back to the relayed path until one becomes eligible again.
+    Exhausted,
+}
+
+/// An ordered group of candidate exit nodes with failover bookkeeping for the active one. See the
+/// module doc.
+pub struct FailoverGroup {
+    candidates: Vec<ExitNode>,
+    active: usize,
+    unhealthy_since: Option<Instant>,
+    backoff: HashMap<PublicKey, Backoff>,
+    config: FailoverConfig,
+}
+
+impl FailoverGroup {
+    /// Builds a group from `candidates`, trying them in the given order. `candidates` must not be
+    /// empty.
+    pub fn new(candidates: Vec<ExitNode>, config: FailoverConfig) -> Self {
+        Self {
+            candidates,
+            active: 0,
+            unhealthy_since: None,
+            backoff: HashMap::new(),
+            config,
+        }
+    }
+
+    /// The currently active candidate, if any were configured.
+    pub fn active(&self) -> Option<&ExitNode> {
+        self.candidates.get(self.active)
+    }
+
+    /// Feeds in the active candidate's most recently observed WireGuard state and decides whether
+    /// to keep it, switch to the next healthy candidate, or report that none are usable.
+    ///
+    /// `PeerState::Connected` is treated as healthy; anything else (this tree's `PeerState` has no
+    /// dedicated "disconnected" variant, see `telio_wg::uapi::Peer::state`) resets the healthy
+    /// clock and, once it's been that way for longer than `handshake_timeout`, triggers a
+    /// failover.
+    pub fn tick(&mut self, active_state: PeerState, now: Instant) -> FailoverDecision {
+        let Some(active_key) = self.active().map(|node| node.public_key) else {
+            return FailoverDecision::Stay;
+        };
+
+        if active_state == PeerState::Connected {
+            self.unhealthy_since = None;
+            self.backoff.remove(&active_key);
+            return FailoverDecision::Stay;
+        }
+
+        let since = *self.unhealthy_since.get_or_insert(now);
+        if now.saturating_duration_since(since) < self.config.handshake_timeout {
+            return FailoverDecision::Stay;
+        }
+
+        match self.next_eligible(now) {
+            Some(index) => {
+                // Only penalize the candidate we're actually demoting it in favor of another one.
+                // Penalizing unconditionally here (even when nothing else is eligible) would
+                // double `active_key`'s backoff on every subsequent tick while stuck with no
+                // alternative, compounding it without bound even though it was never actually
+                // switched away from.
+                self.penalize(active_key, now);
+                self.active = index;
+                self.unhealthy_since = None;
+                FailoverDecision::Switch(self.candidates[index].clone())
+            }
+            None => FailoverDecision::Exhausted,
+        }
+    }
+
+    /// Records a backoff penalty for `key`, doubling (bounded by `config.backoff.maximal`) the
+    /// penalty it'll serve next time it's demoted.
+    fn penalize(&mut self, key: PublicKey, now: Instant) {
+        let entry = self.backoff.entry(key).or_insert(Backoff {
+            next: self.config.backoff.initial,
+            skip_until: now,
+        });
+        entry.skip_until = now + entry.next;
+        let doubled = entry.next.saturating_mul(2);
+        entry.next = match self.config.backoff.maximal {
+            Some(max) => doubled.min(max),
+            None => doubled,
+        };
+    }
+
+    /// The next candidate (after the active one, wrapping around) that isn't currently serving a
+    /// backoff penalty. Excludes `offset == candidates.len()`, which would wrap back onto `active`
+    /// itself -- without that exclusion a single-candidate group (or one where every other
+    /// candidate is backed off) would "switch" to the very candidate that just failed instead of
+    /// reporting [`FailoverDecision::Exhausted`].
+    fn next_eligible(&self, now: Instant) -> Option<usize> {
+        (1..self.candidates.len())
+            .map(|offset| (self.active + offset) % self.candidates.len())
+            .find(|index| {
+                self.candidates
+                    .get(*index)
+                    .map(|candidate| {
+                        self.backoff
+                            .get(&candidate.public_key)
+                            .map_or(true, |backoff| now >= backoff.skip_until)
+                    })
+                    .unwrap_or(false)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use telio_crypto::SecretKey;
+
+    fn node() -> ExitNode {
+        ExitNode {
+            public_key: SecretKey::gen().public(),
+            ..Default::default()
+        }
+    }
+
+    fn config() -> FailoverConfig {
+        FailoverConfig {
+            handshake_timeout: Duration::from_secs(15),
+            backoff: ExponentialBackoffBounds {
+                initial: Duration::from_secs(5),
+                maximal: Some(Duration::from_secs(300)),
+            },
+        }
+    }
+
+    #[test]
+    fn single_candidate_group_reports_exhausted_instead_of_switching_to_itself() {
+        let mut group = FailoverGroup::new(vec![node()], config());
+        let mut now = Instant::now();
+
+        // Stay unhealthy for longer than the timeout so a failover is attempted.
+        let _ = group.tick(PeerState::Connecting, now);
+        now += Duration::from_secs(16);
+
+        assert!(matches!(
+            group.tick(PeerState::Connecting, now),
+            FailoverDecision::Exhausted
+        ));
+    }
+
+    #[test]
+    fn reports_exhausted_when_every_other_candidate_is_backed_off() {
+        // The initial backoff has to outlast a second handshake timeout, or candidate 0 is
+        // eligible again by the time candidate 1 fails.
+        let config = FailoverConfig {
+            backoff: ExponentialBackoffBounds {
+                initial: Duration::from_secs(60),
+                maximal: Some(Duration::from_secs(300)),
+            },
+            ..config()
+        };
+        let mut group = FailoverGroup::new(vec![node(), node()], config);
+        let mut now = Instant::now();
+
+        // First timeout: demotes candidate 0, switches to candidate 1, and penalizes candidate 0.
+        let _ = group.tick(PeerState::Connecting, now);
+        now += Duration::from_secs(16);
+        assert!(matches!(
+            group.tick(PeerState::Connecting, now),
+            FailoverDecision::Switch(_)
+        ));
+
+        // Second timeout: candidate 1 is now active and unhealthy, but candidate 0 is still
+        // serving the backoff penalty from the first demotion, so there's nowhere to switch.
+        let _ = group.tick(PeerState::Connecting, now);
+        now += Duration::from_secs(16);
+        assert!(matches!(
+            group.tick(PeerState::Connecting, now),
+            FailoverDecision::Exhausted
+        ));
+    }
+}
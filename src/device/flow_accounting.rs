@@ -0,0 +1,331 @@
+//! Per-peer flow accounting by inspecting tunneled IP packets.
+//!
+//! `firewall_filter_inbound_packets`/`firewall_filter_outbound_packets` (see `Runtime::start` in
+//! `mod.rs`) already hand every packet crossing the tunnel to `StatefullFirewall` as a
+//! `(peer: &[u8; 32], packet: &[u8])` pair before it's allowed through -- the same seam this module
+//! hooks to build counters, the way the nym crate's userspace datapath parses tunneled packets with
+//! `etherparse`'s `SlicedPacket`/`InternetSlice` to do its own per-flow bookkeeping. [`FlowAccounting::record`]
+//! is called from inside those closures; everything it needs -- the counter map and the packet
+//! parsing -- is self-contained here.
+//!
+//! [`FlowAccounting`] shards its peer map across [`SHARD_COUNT`] `RwLock`-guarded buckets (picked by
+//! hashing the peer's public key, same FNV-1a approach `derp_selection`/`multicast_discovery` use
+//! elsewhere in this module) so concurrent packets for different peers rarely contend on the same
+//! lock; once a peer's entry exists, every counter inside it is a plain atomic, so the steady-state
+//! packet path only takes shared read locks (on the shard, and on the peer's protocol and
+//! destination-prefix maps); a write lock is taken only the first time a peer, protocol or prefix
+//! is seen.
+//!
+//! A packet that isn't a well-formed IPv4/IPv6 datagram (or isn't IP at all) is counted under
+//! [`Protocol::Other`]'s catch-all and otherwise ignored -- parsing never panics on untrusted
+//! tunneled bytes, it just falls back to "unknown".
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use etherparse::{InternetSlice, SlicedPacket};
+use parking_lot::RwLock;
+use telio_crypto::PublicKey;
+
+/// Number of shards [`FlowAccounting`]'s peer map is split across.
+pub const SHARD_COUNT: usize = 16;
+
+/// Caps how many distinct destination prefixes are tracked per peer, so a peer talking to a huge
+/// number of distinct destinations (a port scan, or just a chatty workload) can't grow a peer's
+/// entry unboundedly; once full, the least-traffic prefix is evicted to make room for a new one.
+const MAX_TRACKED_PREFIXES: usize = 64;
+
+/// Which way a packet crossed the tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Coming from the peer, into the local host.
+    Inbound,
+    /// Leaving the local host, towards the peer.
+    Outbound,
+}
+
+/// The inner transport protocol a tunneled packet carries, coarse enough to be a useful breakdown
+/// without tracking every IANA protocol number separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    Icmp,
+    /// Anything else, including packets [`FlowAccounting::record`] couldn't parse as IP at all.
+    Other,
+}
+
+/// A single running total: bytes and packets, both plain atomics, so a counter can be updated
+/// while only a shared read lock (or, for a peer's `rx`/`tx`, no lock) is held.
+#[derive(Debug, Default)]
+struct Counter {
+    bytes: AtomicU64,
+    packets: AtomicU64,
+}
+
+impl Counter {
+    fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> (u64, u64) {
+        (
+            self.bytes.load(Ordering::Relaxed),
+            self.packets.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Running counters for one peer. Looked up once per packet via [`FlowAccounting`]'s sharded map;
+/// `rx`/`tx` are then updated without a lock, the two breakdown maps under a read lock.
+#[derive(Debug, Default)]
+struct PeerFlowStats {
+    rx: Counter,
+    tx: Counter,
+    by_protocol: RwLock<HashMap<Protocol, Counter>>,
+    /// Keyed by a masked destination prefix (see [`prefix_of`]), capped at
+    /// [`MAX_TRACKED_PREFIXES`].
+    by_destination_prefix: RwLock<HashMap<IpAddr, Counter>>,
+}
+
+impl PeerFlowStats {
+    fn record(&self, direction: Direction, bytes: u64, protocol: Protocol, destination: IpAddr) {
+        match direction {
+            Direction::Inbound => self.rx.add(bytes),
+            Direction::Outbound => self.tx.add(bytes),
+        }
+
+        // Counter has no Default-on-insert shortcut under a shared RwLock<HashMap<_, Counter>>
+        // (Counter isn't Clone, being all atomics), so take the write lock only the first time a
+        // key is seen; every later hit for the same key only takes the read lock.
+        if !self.by_protocol.read().contains_key(&protocol) {
+            self.by_protocol
+                .write()
+                .entry(protocol)
+                .or_insert_with(Counter::default);
+        }
+        if let Some(counter) = self.by_protocol.read().get(&protocol) {
+            counter.add(bytes);
+        }
+
+        let prefix = prefix_of(destination);
+        if !self.by_destination_prefix.read().contains_key(&prefix) {
+            let mut prefixes = self.by_destination_prefix.write();
+            if !prefixes.contains_key(&prefix) {
+                if prefixes.len() >= MAX_TRACKED_PREFIXES {
+                    if let Some(least) = prefixes
+                        .iter()
+                        .min_by_key(|(_, counter)| counter.snapshot().0)
+                        .map(|(addr, _)| *addr)
+                    {
+                        prefixes.remove(&least);
+                    }
+                }
+                prefixes.insert(prefix, Counter::default());
+            }
+        }
+        if let Some(counter) = self.by_destination_prefix.read().get(&prefix) {
+            counter.add(bytes);
+        }
+    }
+
+    fn snapshot(&self) -> PeerFlowSnapshot {
+        let (rx_bytes, rx_packets) = self.rx.snapshot();
+        let (tx_bytes, tx_packets) = self.tx.snapshot();
+
+        let mut by_protocol: Vec<(Protocol, u64, u64)> = self
+            .by_protocol
+            .read()
+            .iter()
+            .map(|(protocol, counter)| {
+                let (bytes, packets) = counter.snapshot();
+                (*protocol, bytes, packets)
+            })
+            .collect();
+        by_protocol.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let mut top_destination_prefixes: Vec<(IpAddr, u64, u64)> = self
+            .by_destination_prefix
+            .read()
+            .iter()
+            .map(|(addr, counter)| {
+                let (bytes, packets) = counter.snapshot();
+                (*addr, bytes, packets)
+            })
+            .collect();
+        top_destination_prefixes.sort_by(|a, b| b.1.cmp(&a.1));
+
+        PeerFlowSnapshot {
+            rx_bytes,
+            rx_packets,
+            tx_bytes,
+            tx_packets,
+            by_protocol,
+            top_destination_prefixes,
+        }
+    }
+}
+
+/// A point-in-time snapshot of one peer's flow counters, returned by
+/// [`FlowAccounting::snapshot`]/`Runtime::peer_flow_stats`.
+#[derive(Debug, Clone, Default)]
+pub struct PeerFlowSnapshot {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    /// `(protocol, bytes, packets)`, descending by bytes.
+    pub by_protocol: Vec<(Protocol, u64, u64)>,
+    /// `(prefix, bytes, packets)`, descending by bytes, at most [`MAX_TRACKED_PREFIXES`] entries.
+    pub top_destination_prefixes: Vec<(IpAddr, u64, u64)>,
+}
+
+/// A sharded per-peer flow-counter map. See the module doc.
+#[derive(Debug)]
+pub struct FlowAccounting {
+    shards: Vec<RwLock<HashMap<PublicKey, Arc<PeerFlowStats>>>>,
+}
+
+impl Default for FlowAccounting {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+}
+
+impl FlowAccounting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn shard(&self, peer: &PublicKey) -> &RwLock<HashMap<PublicKey, Arc<PeerFlowStats>>> {
+        &self.shards[shard_index(peer)]
+    }
+
+    fn entry(&self, peer: &PublicKey) -> Arc<PeerFlowStats> {
+        let shard = self.shard(peer);
+        if let Some(stats) = shard.read().get(peer) {
+            return stats.clone();
+        }
+        shard
+            .write()
+            .entry(*peer)
+            .or_insert_with(|| Arc::new(PeerFlowStats::default()))
+            .clone()
+    }
+
+    /// Parses `packet`'s inner IP header and folds its length into `peer`'s counters: overall
+    /// `direction` totals, a protocol breakdown, and a per-destination-prefix breakdown. A packet
+    /// that doesn't parse as IPv4/IPv6 is still counted (under [`Protocol::Other`], with no
+    /// destination prefix recorded), never panics or is dropped from the byte/packet totals.
+    pub fn record(&self, peer: &PublicKey, direction: Direction, packet: &[u8]) {
+        let stats = self.entry(peer);
+        let bytes = packet.len() as u64;
+
+        match SlicedPacket::from_ip(packet) {
+            Ok(sliced) => {
+                let protocol = sliced
+                    .transport
+                    .as_ref()
+                    .map(protocol_of)
+                    .unwrap_or(Protocol::Other);
+                match sliced.ip {
+                    Some(InternetSlice::Ipv4(ipv4, _)) => {
+                        let destination = IpAddr::V4(ipv4.destination_addr());
+                        stats.record(direction, bytes, protocol, destination);
+                    }
+                    Some(InternetSlice::Ipv6(ipv6, _)) => {
+                        let destination = IpAddr::V6(ipv6.destination_addr());
+                        stats.record(direction, bytes, protocol, destination);
+                    }
+                    None => stats.rx_tx_only(direction, bytes, Protocol::Other),
+                }
+            }
+            Err(_) => stats.rx_tx_only(direction, bytes, Protocol::Other),
+        }
+    }
+
+    /// Returns a snapshot of `peer`'s counters, if any packets have been recorded for it yet.
+    pub fn snapshot(&self, peer: &PublicKey) -> Option<PeerFlowSnapshot> {
+        self.shard(peer).read().get(peer).map(|stats| stats.snapshot())
+    }
+
+    /// Drops a peer's counters, e.g. once it's no longer part of the meshnet config.
+    pub fn remove(&self, peer: &PublicKey) {
+        self.shard(peer).write().remove(peer);
+    }
+
+    /// Drops every tracked peer not present in `current_peers`, called from `set_config` so a
+    /// removed peer's counters don't linger forever.
+    pub fn retain_only(&self, current_peers: &HashSet<PublicKey>) {
+        for shard in &self.shards {
+            shard.write().retain(|peer, _| current_peers.contains(peer));
+        }
+    }
+}
+
+impl PeerFlowStats {
+    /// Records a packet whose destination couldn't be determined (no parseable IP header): only
+    /// the direction total and protocol breakdown are updated, no destination-prefix entry.
+    fn rx_tx_only(&self, direction: Direction, bytes: u64, protocol: Protocol) {
+        match direction {
+            Direction::Inbound => self.rx.add(bytes),
+            Direction::Outbound => self.tx.add(bytes),
+        }
+        if !self.by_protocol.read().contains_key(&protocol) {
+            self.by_protocol
+                .write()
+                .entry(protocol)
+                .or_insert_with(Counter::default);
+        }
+        if let Some(counter) = self.by_protocol.read().get(&protocol) {
+            counter.add(bytes);
+        }
+    }
+}
+
+fn protocol_of(transport: &etherparse::TransportSlice) -> Protocol {
+    match transport {
+        etherparse::TransportSlice::Tcp(_) => Protocol::Tcp,
+        etherparse::TransportSlice::Udp(_) => Protocol::Udp,
+        etherparse::TransportSlice::Icmpv4(_) | etherparse::TransportSlice::Icmpv6(_) => {
+            Protocol::Icmp
+        }
+        _ => Protocol::Other,
+    }
+}
+
+/// Masks `addr` down to a /24 (IPv4) or /64 (IPv6) prefix, so counters group by subnet rather than
+/// by every individual destination address.
+fn prefix_of(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            IpAddr::V4(std::net::Ipv4Addr::new(octets[0], octets[1], octets[2], 0))
+        }
+        IpAddr::V6(v6) => {
+            let mut segments = v6.segments();
+            segments[4..].fill(0);
+            IpAddr::V6(std::net::Ipv6Addr::from(segments))
+        }
+    }
+}
+
+/// FNV-1a-based shard selection, same non-cryptographic hash other modules in this directory use for
+/// deterministic bucketing (see `derp_selection::seed_for`).
+fn shard_index(peer: &PublicKey) -> usize {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in &peer.0 {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash as usize) % SHARD_COUNT
+}
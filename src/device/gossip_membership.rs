@@ -0,0 +1,131 @@
+//! Incremental meshnet membership propagation via gossip.
+//!
+//! `set_config` replaces `RequestedState.meshnet_config` wholesale on every call, which is fine as
+//! the authoritative seed/override path but doesn't scale to propagating a single membership
+//! change (one node joining, leaving, or updating its endpoint) across a large meshnet -- that
+//! forces every node through a full control-plane round trip. [`MemberTable`] is the data-
+//! structure half of an incremental alternative: each node keeps a versioned table of member
+//! entries and periodically (driven off `Runtime.polling_interval`) picks a connected peer,
+//! exchanges a digest of table versions, and pulls only the entries that peer has newer versions
+//! of, merging by taking the higher version per key. The peer is picked by a counter that advances
+//! once per round (see `Runtime::run_gossip_round`), not at random.
+//!
+//! The digest/delta exchange itself is carried on a dedicated `Multiplexer` channel, registered
+//! the same way `UdpProxy`'s `relay` and `CrossPingCheck`'s `intercoms` are (see
+//! `Runtime::start` in `mod.rs`): [`GossipWireMessage`] is the message exchanged, and
+//! `Runtime::run_gossip_round`/`Runtime::handle_gossip_message` (in `mod.rs`) are where it's
+//! driven from `Runtime.polling_interval` and the channel's receive side, respectively.
+
+use std::{collections::BTreeMap, net::IpAddr};
+
+use telio_crypto::PublicKey;
+
+/// One digest/delta round's payload, addressed to/from a specific peer. `Multiplexer`'s channel
+/// carries this to/from whichever peer `peer` names, the same way `CrossPingCheck`'s messages are
+/// addressed -- there's no separate per-peer channel, so every message says who it's for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GossipWireMessage {
+    pub peer: PublicKey,
+    pub message: GossipMessage,
+}
+
+/// The two message shapes a gossip round exchanges: a digest to ask what's new, and the delta
+/// entries that answer it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipMessage {
+    /// "Here's the version I have for each member I know about; send me anything newer."
+    Digest(Digest),
+    /// The entries the sender has that are newer than what the digest asked about.
+    Delta(BTreeMap<PublicKey, MemberEntry>),
+}
+
+/// One member's last known identity/location, plus the version it was last updated at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberEntry {
+    pub hostname: String,
+    pub ip_addresses: Vec<IpAddr>,
+    pub version: u64,
+}
+
+/// A compact summary of a [`MemberTable`]: just the version for every known member, cheap enough
+/// to exchange with every gossip partner every round.
+pub type Digest = BTreeMap<PublicKey, u64>;
+
+/// A node's local view of meshnet membership, keyed by public key.
+#[derive(Debug, Default)]
+pub struct MemberTable {
+    members: BTreeMap<PublicKey, MemberEntry>,
+    next_version: u64,
+}
+
+impl MemberTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a local change (add, or an update to an existing member's hostname/addresses),
+    /// bumping it to a new, table-wide-unique version.
+    pub fn upsert(&mut self, public_key: PublicKey, hostname: String, ip_addresses: Vec<IpAddr>) {
+        self.next_version += 1;
+        self.members.insert(
+            public_key,
+            MemberEntry {
+                hostname,
+                ip_addresses,
+                version: self.next_version,
+            },
+        );
+    }
+
+    /// Drops a member that left the meshnet.
+    pub fn remove(&mut self, public_key: &PublicKey) {
+        self.members.remove(public_key);
+    }
+
+    pub fn digest(&self) -> Digest {
+        self.members.iter().map(|(key, entry)| (*key, entry.version)).collect()
+    }
+
+    /// Given a peer's digest, returns the entries we have that are newer than what they reported
+    /// (or that they're missing entirely) -- i.e. what we'd push to them this round.
+    pub fn entries_newer_than(&self, their_digest: &Digest) -> BTreeMap<PublicKey, MemberEntry> {
+        self.members
+            .iter()
+            .filter(|(key, entry)| {
+                their_digest
+                    .get(*key)
+                    .map_or(true, |their_version| entry.version > *their_version)
+            })
+            .map(|(key, entry)| (*key, entry.clone()))
+            .collect()
+    }
+
+    /// Merges delta entries received from a peer: an incoming entry replaces our local one only
+    /// if its version is strictly newer, so gossip converges regardless of delivery order.
+    pub fn merge(&mut self, delta: BTreeMap<PublicKey, MemberEntry>) {
+        for (key, entry) in delta {
+            let is_newer = self
+                .members
+                .get(&key)
+                .map_or(true, |existing| entry.version > existing.version);
+            if is_newer {
+                self.next_version = self.next_version.max(entry.version);
+                self.members.insert(key, entry);
+            }
+        }
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = (&PublicKey, &MemberEntry)> {
+        self.members.iter()
+    }
+}
+
+/// Picks which of the currently connected peers to gossip with this round. `pick` is an
+/// externally supplied index (e.g. from whatever RNG the caller already uses elsewhere), so this
+/// module doesn't need its own randomness source.
+pub fn pick_gossip_partner(connected: &[PublicKey], pick: usize) -> Option<PublicKey> {
+    if connected.is_empty() {
+        return None;
+    }
+    connected.get(pick % connected.len()).copied()
+}
@@ -0,0 +1,191 @@
+//! Gossip-based endpoint-candidate exchange for large meshnets, so candidates can diffuse
+//! peer-to-peer instead of only propagating through DERP and full `set_config` pushes.
+//!
+//! [`PeerSamplingView`] is a Basalt-style uniform peer-sampling view: a fixed-size set of `k`
+//! slots, each with its own seed `seed_i`. A candidate peer occupies slot `i` only if it currently
+//! minimizes the ranking hash `H(seed_i XOR peer_id)` among everything [`PeerSamplingView::observe`]
+//! has seen for that slot -- a bottom-k sample. This bounds how much of the view an attacker who
+//! floods many fake identities can take over: winning a slot costs work proportional to how many
+//! identities they inject, not a fixed cost to dominate the whole view, since each slot samples
+//! independently under a different seed. [`PeerSamplingView::rotate_seeds`] periodically replaces
+//! every slot's seed (clearing its winner), so the view can't get permanently stuck on a stale or
+//! adversarial sample.
+//!
+//! A round (see [`PeerSamplingView::ingest`]) pulls the view held by each currently-held peer,
+//! re-inserts every candidate it reports via `observe`, and piggybacks this node's own
+//! `{pubkey, endpoint candidates}` onto what gets sent out ([`PeerSamplingView::outgoing_payload`]),
+//! so candidates diffuse across the mesh in O(log n) rounds without a central point. Every entry
+//! [`PeerSamplingView::ingest`] is asked to admit is checked against the caller's meshnet
+//! membership first -- an entry for a pubkey that isn't a known peer is dropped, same as
+//! `multicast_discovery`'s admission rule.
+//!
+//! What's left as an integration seam: actually carrying [`GossipPayload`] over the wire (a
+//! registered `Multiplexer` channel, such as `gossip_membership` and `identify` already use) and a
+//! `FeatureDirect` option to select this subsystem, since `telio_model`'s `api_config` has no
+//! source file in this checkout to add a field to. This module implements everything that doesn't
+//! depend on either: the view itself, the ranking/eviction rule, seed rotation, and membership
+//! validation on ingest. Feeding [`PeerSamplingView::view`]'s output into the same candidate set
+//! `CrossPingCheck`/`UpgradeSync` consume from `local_interfaces_endpoint_provider`/
+//! `stun_endpoint_provider`/`upnp_endpoint_provider` is the other half of the seam: those providers
+//! and `EndpointProvider` itself live in `telio_traversal`, not present here either.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
+
+use telio_crypto::PublicKey;
+
+/// Tunables for a [`PeerSamplingView`].
+#[derive(Debug, Clone, Copy)]
+pub struct PeerSamplingConfig {
+    /// Number of slots `k` in the view.
+    pub view_size: usize,
+}
+
+impl Default for PeerSamplingConfig {
+    fn default() -> Self {
+        Self { view_size: 16 }
+    }
+}
+
+/// One slot's current winner: the peer minimizing this slot's ranking hash among everything
+/// observed so far, and its endpoint candidates.
+#[derive(Debug, Clone)]
+struct SlotWinner {
+    peer: PublicKey,
+    rank: u64,
+    candidates: Vec<SocketAddr>,
+}
+
+/// A Basalt-style uniform peer-sampling view. See the module doc.
+#[derive(Debug)]
+pub struct PeerSamplingView {
+    seeds: Vec<u64>,
+    slots: Vec<Option<SlotWinner>>,
+}
+
+impl PeerSamplingView {
+    /// Builds an empty view with `config.view_size` slots, seeded from `seeds` (taken in order,
+    /// one per slot; callers should supply these from whatever RNG they already have, same as
+    /// `gossip_membership::pick_gossip_partner` takes an externally-supplied index).
+    pub fn new(config: PeerSamplingConfig, seeds: impl IntoIterator<Item = u64>) -> Self {
+        let seeds: Vec<u64> = seeds.into_iter().take(config.view_size).collect();
+        let slots = seeds.iter().map(|_| None).collect();
+        Self { seeds, slots }
+    }
+
+    /// Considers `peer` (and its current endpoint candidates) for every slot, replacing a slot's
+    /// winner if `peer` ranks lower under that slot's seed. A peer already occupying some other
+    /// slot is not excluded from winning more -- each slot samples independently.
+    pub fn observe(&mut self, peer: PublicKey, candidates: Vec<SocketAddr>) {
+        for (seed, slot) in self.seeds.iter().zip(self.slots.iter_mut()) {
+            let rank = rank_hash(*seed, &peer);
+            let should_replace = match slot {
+                Some(winner) => rank < winner.rank || (rank == winner.rank && peer < winner.peer),
+                None => true,
+            };
+            if should_replace {
+                *slot = Some(SlotWinner {
+                    peer,
+                    rank,
+                    candidates: candidates.clone(),
+                });
+            }
+        }
+    }
+
+    /// Pulls a remote peer's reported view and re-inserts every entry, validating each claimed
+    /// public key against `known_peers` first -- an entry for a pubkey that isn't already a
+    /// configured meshnet peer is dropped rather than observed.
+    pub fn ingest(
+        &mut self,
+        remote_view: impl IntoIterator<Item = (PublicKey, Vec<SocketAddr>)>,
+        known_peers: &HashSet<PublicKey>,
+    ) {
+        for (peer, candidates) in remote_view {
+            if known_peers.contains(&peer) {
+                self.observe(peer, candidates);
+            }
+        }
+    }
+
+    /// Periodically replaces every slot's seed, clearing its current winner so the next round of
+    /// `observe`/`ingest` calls re-evaluates it from scratch -- without this, a slot that landed on
+    /// a stale or malicious peer early would never move again. `new_seeds` is taken in order, one
+    /// per slot, same convention as [`PeerSamplingView::new`]; a slot with no corresponding entry
+    /// keeps its old seed.
+    pub fn rotate_seeds(&mut self, new_seeds: impl IntoIterator<Item = u64>) {
+        let mut new_seeds = new_seeds.into_iter();
+        for (seed, slot) in self.seeds.iter_mut().zip(self.slots.iter_mut()) {
+            if let Some(new_seed) = new_seeds.next() {
+                *seed = new_seed;
+            }
+            *slot = None;
+        }
+    }
+
+    /// This round's distinct view, deduplicated across slots: every peer currently winning at
+    /// least one slot, with its endpoint candidates. This is what should feed into the same
+    /// candidate set direct-connection setup consumes from the STUN/UPnP/local-interface
+    /// providers.
+    pub fn view(&self) -> Vec<(PublicKey, Vec<SocketAddr>)> {
+        let mut seen = HashMap::new();
+        for winner in self.slots.iter().flatten() {
+            seen.entry(winner.peer)
+                .or_insert_with(|| winner.candidates.clone());
+        }
+        seen.into_iter().collect()
+    }
+
+    /// Builds the payload this node would send out this round: its current view, plus its own
+    /// `{pubkey, endpoint candidates}` piggybacked on alongside it so candidates diffuse across the
+    /// mesh without a dedicated announce message.
+    pub fn outgoing_payload(
+        &self,
+        self_key: PublicKey,
+        self_candidates: Vec<SocketAddr>,
+    ) -> GossipPayload {
+        GossipPayload {
+            view: self.view(),
+            self_entry: (self_key, self_candidates),
+        }
+    }
+}
+
+/// What a gossip round exchanges with a peer: see [`PeerSamplingView::outgoing_payload`].
+#[derive(Debug, Clone)]
+pub struct GossipPayload {
+    pub view: Vec<(PublicKey, Vec<SocketAddr>)>,
+    pub self_entry: (PublicKey, Vec<SocketAddr>),
+}
+
+/// Deterministic initial seeds for a fresh [`PeerSamplingView`]'s `config.view_size` slots,
+/// derived from this node's own public key so two runs of the same node start from the same
+/// sample (stable across restarts, like `derp_selection::weighted_order`'s seeding) while two
+/// different nodes don't share a view.
+pub fn seed_slots(public_key: &PublicKey, view_size: usize) -> Vec<u64> {
+    (0..view_size as u64)
+        .map(|i| {
+            let mut hash: u64 = 0xcbf29ce484222325 ^ i;
+            for &byte in &public_key.0 {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+            hash
+        })
+        .collect()
+}
+
+/// The ranking hash `H(seed XOR peer_id)`, an FNV-1a fold over the seed XORed into every byte of
+/// the peer's public key -- same non-cryptographic-checksum approach `multicast_discovery::checksum`
+/// uses, since this only needs to be a well-distributed ranking function, not a MAC.
+fn rank_hash(seed: u64, peer: &PublicKey) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let seed_bytes = seed.to_be_bytes();
+    for (i, byte) in peer.0.iter().enumerate() {
+        hash ^= (byte ^ seed_bytes[i % seed_bytes.len()]) as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
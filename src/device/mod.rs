@@ -1,5 +1,34 @@
+mod auto_route;
+mod derp_selection;
+mod direct_budget;
+mod dns_blocklist;
+mod executor;
+mod exit_failover;
+mod flow_accounting;
+mod gossip_membership;
+mod gossip_peer_sampling;
+mod identify;
+mod multicast_discovery;
+mod named_endpoint;
+mod path_state;
+mod pcp_endpoint;
+mod reflexive_consensus;
+mod upgrade_tiebreak;
 mod wg_controller;
 
+use auto_route::{AutoRouteConfig, AutoRouteManager, RouteTarget};
+use direct_budget::{Assignment, DirectBudgetConfig, PeerRank};
+use dns_blocklist::{DnsBlockAction, DnsBlocklistConfig};
+use executor::{Executor, TokioExecutor};
+use exit_failover::{FailoverConfig, FailoverDecision, FailoverGroup};
+use flow_accounting::{Direction as FlowDirection, FlowAccounting, PeerFlowSnapshot};
+use gossip_membership::{GossipMessage, GossipWireMessage, MemberTable};
+use gossip_peer_sampling::{seed_slots, PeerSamplingConfig, PeerSamplingView};
+use identify::{IdentifyMessage, IdentifyState, IdentifyWireMessage, NetworkId};
+use named_endpoint::NamedEndpoint;
+use path_state::PeerPathState;
+use reflexive_consensus::ReflexiveConsensus;
+
 use async_trait::async_trait;
 use telio_crypto::{PublicKey, SecretKey};
 use telio_firewall::firewall::{Firewall, StatefullFirewall};
@@ -44,12 +73,12 @@ use telio_dns::bind_tun;
 use wg::uapi::{self, PeerState};
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     future::Future,
     io::{Error as IoError, ErrorKind},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use cfg_if::cfg_if;
@@ -145,6 +174,35 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 pub trait EventCb: Fn(Box<Event>) + Send + 'static {}
 impl<T> EventCb for T where T: Fn(Box<Event>) + Send + 'static {}
 
+/// A point-in-time snapshot of the runtime's internal state, for diagnostics. See
+/// [`Device::inspect`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Every WireGuard peer currently known to the adapter.
+    pub peers: Vec<PeerSnapshot>,
+    /// Whether the DERP relay currently has a configuration to connect with.
+    pub derp_configured: bool,
+    /// The exit node currently requested via [`Device::connect_exit_node`], if any.
+    pub requested_exit_node: Option<PublicKey>,
+    /// Whether a meshnet configuration is currently set via [`Device::set_config`].
+    pub meshnet_configured: bool,
+}
+
+/// Per-peer state reported as part of a [`Snapshot`].
+#[derive(Debug, Clone)]
+pub struct PeerSnapshot {
+    /// The peer's primary identifier.
+    pub public_key: PublicKey,
+    /// The peer's last-reported WireGuard state.
+    pub state: PeerState,
+    /// The peer's last-reported endpoint, if any.
+    pub endpoint: Option<SocketAddr>,
+    /// Whether traffic to this peer is currently relayed or direct.
+    pub path_type: PathType,
+    /// Time elapsed since the last successful handshake, if one has ever completed.
+    pub time_since_last_handshake: Option<Duration>,
+}
+
 #[derive(Clone, Default)]
 pub struct DeviceConfig {
     pub private_key: SecretKey,
@@ -152,6 +210,16 @@ pub struct DeviceConfig {
     pub fwmark: Option<u32>,
     pub name: Option<String>,
     pub tun: Option<Tun>,
+    /// Opt in to automatic OS routing-table management for the tunnel, see [`AutoRouteConfig`].
+    pub auto_route: Option<AutoRouteConfig>,
+    /// Bounds how many peers are kept upgraded to a direct connection at once, see
+    /// [`DirectBudgetConfig`]. `None` means unbounded (today's behavior).
+    pub direct_budget: Option<DirectBudgetConfig>,
+    /// Where the runtime's background tasks (e.g. `log_nat`'s NAT probe) get spawned. `None`
+    /// (the default) falls back to [`TokioExecutor`], matching today's `tokio::spawn`-based
+    /// behavior; set this to embed libtelio in a host that drives its own event loop instead of
+    /// letting it spin up a hidden thread pool. See the `executor` module.
+    pub executor: Option<Arc<dyn Executor>>,
 }
 
 pub struct Device {
@@ -180,6 +248,11 @@ pub struct RequestedState {
     // is disconnection from VPN node
     pub last_exit_node: Option<ExitNode>,
 
+    // Ordered candidate list and failover bookkeeping, set once by
+    // `libtelio.connect_exit_node_group(...)`, see the `exit_failover` module. `None` means no
+    // failover group is active, the default: a plain `connect_exit_node` call clears it to `None`.
+    pub exit_failover: Option<FailoverGroup>,
+
     // Local DNS resolver config, passed by libtelio.enable_magic_dns(...)
     // this is a last known list of dns forward servers, to change back to in
     // case of disconnecting from non-vpn exit peer
@@ -188,6 +261,16 @@ pub struct RequestedState {
     // Wireguard stun server that should be currently used
     pub wg_stun_server: Option<StunServer>,
 
+    // Symmetric-NAT flag for reflexive-address consensus (see `reflexive_consensus`): agreement on
+    // IP but not port across queried STUN servers. Nothing in this runtime sets it, so it stays
+    // `false`.
+    pub symmetric_nat: bool,
+
+    // This meshnet's identifier, if the identify misconfiguration check (see `identify`) is opted
+    // into via `Device::set_network_id`. `None` (the default) disables it entirely. This never
+    // gates a peer's direct path either way -- see the `identify` module doc.
+    pub network_id: Option<NetworkId>,
+
     // Requested keepalive periods
     pub(crate) keepalive_periods: FeaturePersistentKeepalive,
 }
@@ -221,6 +304,42 @@ pub struct Entities {
 
     // Nurse
     nurse: Option<Arc<Nurse>>,
+
+    // Opt-in automatic OS routing-table management for the tunnel, see [`auto_route`]
+    auto_route: Option<AutoRouteManager>,
+
+    // Reflexive-address consensus state, see [`reflexive_consensus`]. Only ever reset (on
+    // `notify_network_change`); nothing in this runtime feeds it STUN observations
+    reflexive_consensus: Mutex<ReflexiveConsensus>,
+
+    // Local view of meshnet membership, incrementally propagated between peers, see
+    // [`gossip_membership`]
+    gossip_membership: Mutex<MemberTable>,
+
+    // Basalt-style peer-sampling view of endpoint candidates, see [`gossip_peer_sampling`]. Only
+    // constructed here: nothing in this runtime observes into it or sends it to peers
+    peer_sampling: Mutex<PeerSamplingView>,
+
+    // Bounds how many peers are kept upgraded to direct at once, see [`direct_budget`]
+    direct_budget: Option<DirectBudgetConfig>,
+
+    // Most recently computed direct/relayed assignment, see [`Runtime::run_direct_budget_maintenance`]
+    direct_assignment: Mutex<HashMap<PublicKey, Assignment>>,
+
+    // Currently requested domain blocklist for magic DNS filtering, see [`dns_blocklist`]
+    dns_blocklist: Mutex<DnsBlocklistConfig>,
+
+    // Per-peer identify-handshake outcome, see [`identify`]. Only consulted when
+    // `RequestedState.network_id` is `Some`.
+    identify_state: Mutex<HashMap<PublicKey, IdentifyState>>,
+
+    // Where background tasks get spawned, see [`DeviceConfig::executor`] and the `executor`
+    // module. Defaults to [`TokioExecutor`] when the config doesn't supply one.
+    executor: Arc<dyn Executor>,
+
+    // Per-peer byte/packet counters, protocol and destination-prefix breakdown, derived from
+    // inspecting tunneled IP packets, see [`flow_accounting`]
+    flow_accounting: Arc<FlowAccounting>,
 }
 
 impl Entities {
@@ -242,6 +361,11 @@ impl Entities {
     pub fn upgrade_sync(&self) -> Option<&Arc<UpgradeSync>> {
         self.direct.as_ref().map(|d| &d.upgrade_sync)
     }
+
+    /// Spawns `future` on the configured [`Executor`], same as `tokio::spawn` used to.
+    fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        self.executor.spawn(Box::pin(future));
+    }
 }
 
 pub struct DirectEntities {
@@ -329,6 +453,17 @@ struct Runtime {
     /// Some of the events are time based, so just poll the whole state from time to time
     polling_interval: Interval,
 
+    /// Digest/delta exchange channel for [`gossip_membership::MemberTable`], see that module's
+    /// doc. Driven from `polling_interval` (send) and its own receive arm in `wait_with_update`.
+    gossip_channel: Chan<GossipWireMessage>,
+
+    /// Index [`gossip_membership::pick_gossip_partner`] tries next, advanced every round.
+    gossip_pick: usize,
+
+    /// Identify-handshake exchange channel, see the `identify` module doc. Driven the same way as
+    /// `gossip_channel`.
+    identify_channel: Chan<IdentifyWireMessage>,
+
     #[cfg(test)]
     /// MockedAdapter (tests)
     test_env: telio_wg::tests::Env,
@@ -406,6 +541,24 @@ impl Device {
         })
     }
 
+    /// Returns a [`Snapshot`] of the runtime's current internal state (WireGuard peers and their
+    /// path type, DERP configuration status, requested exit node, meshnet configuration status),
+    /// for diagnostics.
+    pub fn inspect(&self) -> Result<Snapshot> {
+        self.art()?.block_on(async {
+            task_exec!(self.rt()?, async move |s| Ok(s.inspect().await)).await?
+        })
+    }
+
+    /// Returns a [`PeerFlowSnapshot`] of `peer`'s tunneled traffic so far: bytes/packets per
+    /// direction, a protocol breakdown, and its top destination prefixes by traffic volume. `None`
+    /// if no packet has been recorded for `peer` yet.
+    pub fn peer_flow_stats(&self, peer: PublicKey) -> Result<Option<PeerFlowSnapshot>> {
+        self.art()?.block_on(async {
+            task_exec!(self.rt()?, async move |s| Ok(s.peer_flow_stats(peer).await)).await?
+        })
+    }
+
     pub fn start(&mut self, config: &DeviceConfig) -> Result {
         if self.is_running() {
             return Err(Error::AlreadyStarted);
@@ -563,6 +716,34 @@ impl Device {
         })
     }
 
+    /// Connect to an ordered group of candidate exit nodes, with automatic failover
+    ///
+    /// Connects to `candidates[0]` the same way [`Device::connect_exit_node`] would. From then on,
+    /// if the active candidate's WireGuard handshake doesn't stay up, the runtime automatically
+    /// demotes it and promotes the next candidate in the list that isn't currently serving a
+    /// backoff penalty (see the `exit_failover` module), emitting a [`Node`] event for the switch.
+    /// If every candidate is unhealthy or backed off, the tunnel falls back to the relayed path
+    /// until one becomes eligible again.
+    ///
+    /// Calling [`Device::connect_exit_node`], [`Device::disconnect_exit_node`] or
+    /// [`Device::disconnect_exit_nodes`] clears any group set up by this call.
+    pub fn connect_exit_node_group(&self, candidates: Vec<ExitNode>) -> Result {
+        self.art()?.block_on(async {
+            let _wireguard_interface: Arc<DynamicWg> = task_exec!(self.rt()?, async move |rt| {
+                rt.connect_exit_node_group(candidates).await?;
+                Ok(rt.entities.wireguard_interface.clone())
+            })
+            .await
+            .map_err(Error::from)?;
+
+            // TODO: delete this as sockets are protected from within boringtun itself
+            #[cfg(not(windows))]
+            self.protect_from_vpn(&*_wireguard_interface).await?;
+
+            Ok(())
+        })
+    }
+
     /// Disconnect from exit node
     ///
     /// Undoes the effects of calling device::connect_exit_node(), matching the node by public key
@@ -621,6 +802,36 @@ impl Device {
         })
     }
 
+    /// Sets (or clears, with `None`) this meshnet's network ID
+    ///
+    /// Opts into (or out of) the identify exchange: while set, the network ID is sent to every
+    /// connected peer each polling tick, and whether each peer's reply matches it is recorded and
+    /// logged. A mismatch gates nothing: path selection and `Node` events are the same either way.
+    /// Disabled by default (`None`).
+    pub fn set_network_id(&self, network_id: Option<[u8; 32]>) -> Result {
+        self.art()?.block_on(async {
+            task_exec!(self.rt()?, async move |rt| {
+                Ok(rt.set_network_id(network_id).await)
+            })
+            .await?
+        })
+    }
+
+    /// Stores the requested magic DNS domain blocklist in the runtime
+    ///
+    /// Each entry of `domains` names a domain together with all its subdomains (exact-suffix
+    /// form), and `action` is NXDOMAIN or the given sink address; an empty list is the disabled
+    /// state. The running magic DNS resolver does not read this setting, so no query is filtered
+    /// or answered differently because of this call.
+    pub fn set_dns_blocklist(&self, domains: Vec<String>, action: Option<DnsBlockAction>) -> Result {
+        self.art()?.block_on(async {
+            task_exec!(self.rt()?, async move |rt| {
+                Ok(rt.set_dns_blocklist(domains, action).await)
+            })
+            .await?
+        })
+    }
+
     /// A artificial method causing panics
     ///
     /// Used only for testing purposes
@@ -698,13 +909,22 @@ impl Runtime {
         protect: Option<Protect>,
     ) -> Result<Self> {
         let firewall = Arc::new(StatefullFirewall::new());
+        let flow_accounting = Arc::new(FlowAccounting::new());
         let firewall_filter_inbound_packets = {
             let fw = firewall.clone();
-            move |peer: &[u8; 32], packet: &[u8]| fw.process_inbound_packet(peer, packet)
+            let flow_accounting = flow_accounting.clone();
+            move |peer: &[u8; 32], packet: &[u8]| {
+                flow_accounting.record(&PublicKey(*peer), FlowDirection::Inbound, packet);
+                fw.process_inbound_packet(peer, packet)
+            }
         };
         let firewall_filter_outbound_packets = {
             let fw = firewall.clone();
-            move |peer: &[u8; 32], packet: &[u8]| fw.process_outbound_packet(peer, packet)
+            let flow_accounting = flow_accounting.clone();
+            move |peer: &[u8; 32], packet: &[u8]| {
+                flow_accounting.record(&PublicKey(*peer), FlowDirection::Outbound, packet);
+                fw.process_outbound_packet(peer, packet)
+            }
         };
 
         let socket_pool = Arc::new({
@@ -727,6 +947,11 @@ impl Runtime {
             relay: multiplexer.get_channel().await?,
         }));
 
+        // Channels for this module's own peer-to-peer exchanges, registered on the multiplexer
+        // the same way `proxy`'s `relay` channel above is.
+        let gossip_channel: Chan<GossipWireMessage> = multiplexer.get_channel().await?;
+        let identify_channel: Chan<IdentifyWireMessage> = multiplexer.get_channel().await?;
+
         // Start Derp client
         let derp_events = McChan::default();
         let derp = Arc::new(DerpRelay::start_with(
@@ -1003,6 +1228,27 @@ impl Runtime {
                 direct,
                 socket_pool,
                 nurse,
+                auto_route: config
+                    .auto_route
+                    .map(|auto_route_config| AutoRouteManager::new(auto_route_config, config.name.clone())),
+                reflexive_consensus: Mutex::new(ReflexiveConsensus::new()),
+                gossip_membership: Mutex::new(MemberTable::new()),
+                peer_sampling: Mutex::new(PeerSamplingView::new(
+                    PeerSamplingConfig::default(),
+                    seed_slots(
+                        &config.private_key.public(),
+                        PeerSamplingConfig::default().view_size,
+                    ),
+                )),
+                direct_budget: config.direct_budget,
+                direct_assignment: Mutex::new(HashMap::new()),
+                dns_blocklist: Mutex::new(DnsBlocklistConfig::disabled()),
+                identify_state: Mutex::new(HashMap::new()),
+                executor: config
+                    .executor
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(TokioExecutor)),
+                flow_accounting,
             },
             event_listeners: EventListeners {
                 wg_endpoint_publish_event_subscriber: wg_endpoint_publish_events.rx,
@@ -1017,6 +1263,9 @@ impl Runtime {
                 nurse_collection_trigger_publisher: collection_trigger_ch,
             },
             polling_interval: interval_at(tokio::time::Instant::now(), Duration::from_secs(5)),
+            gossip_channel,
+            gossip_pick: 0,
+            identify_channel,
             #[cfg(test)]
             test_env: wg::tests::Env {
                 analytics: analytics_ch,
@@ -1038,6 +1287,40 @@ impl Runtime {
         Ok(nodes)
     }
 
+    /// Builds a [`Snapshot`] of the runtime's current state, for diagnostics. See [`Device::inspect`].
+    async fn inspect(&self) -> Result<Snapshot> {
+        let wgi = self.entities.wireguard_interface.get_interface().await?;
+
+        let mut peers = Vec::with_capacity(wgi.peers.len());
+        for peer in wgi.peers.values() {
+            let path_type = self.resolve_path_type(peer).await;
+            peers.push(PeerSnapshot {
+                public_key: peer.public_key,
+                state: peer.state(),
+                endpoint: peer.endpoint,
+                path_type,
+                time_since_last_handshake: peer.time_since_last_handshake,
+            });
+        }
+
+        Ok(Snapshot {
+            peers,
+            derp_configured: self.entities.derp.get_config().await.is_some(),
+            requested_exit_node: self
+                .requested_state
+                .exit_node
+                .as_ref()
+                .map(|node| node.public_key),
+            meshnet_configured: self.requested_state.meshnet_config.is_some(),
+        })
+    }
+
+    /// Returns a [`PeerFlowSnapshot`] of `peer`'s tunneled-traffic counters, if any packet has
+    /// been recorded for it yet. See [`Device::peer_flow_stats`] and the `flow_accounting` module.
+    async fn peer_flow_stats(&self, peer: PublicKey) -> Option<PeerFlowSnapshot> {
+        self.entities.flow_accounting.snapshot(&peer)
+    }
+
     async fn upsert_dns_peers(&self) -> Result {
         if let (Some(dns), peers) = (
             &self.entities.dns.lock().await.resolver,
@@ -1114,6 +1397,11 @@ impl Runtime {
 
         self.entities.derp.reconnect().await;
         self.log_nat().await;
+
+        // Addresses observed before a network change are no longer meaningful; re-run consensus
+        // from scratch once the endpoint providers start publishing fresh candidates again.
+        self.entities.reflexive_consensus.lock().await.reset();
+
         Ok(())
     }
 
@@ -1186,6 +1474,46 @@ impl Runtime {
         Ok(())
     }
 
+    /// Sets (or clears, with `None`) this meshnet's network ID, opting into (or out of) the
+    /// identify misconfiguration check. See the `identify` module doc -- this does not gate
+    /// anything, it only controls whether a mismatch gets recorded for diagnostics.
+    ///
+    /// Clears any previously recorded per-peer identify state, since it was computed against
+    /// whatever network ID was previously in effect.
+    async fn set_network_id(&mut self, network_id: Option<NetworkId>) {
+        self.requested_state.network_id = network_id;
+        self.entities.identify_state.lock().await.clear();
+    }
+
+    /// Feeds in the result of an identify exchange with `peer`: a message it sent, compared
+    /// against our own `network_id`. See the `identify` module doc for why this is a diagnostic
+    /// check, not an authentication result. Called from `wait_with_update`'s `identify_channel`
+    /// receive arm whenever a peer's `IdentifyMessage` arrives.
+    async fn handle_identify_response(&self, peer: PublicKey, message: IdentifyMessage) {
+        let Some(expected) = self.requested_state.network_id else {
+            return;
+        };
+        let state = message.verify(&expected);
+        telio_log_debug!("identify: {:?} is now {:?}", peer, state);
+        self.entities.identify_state.lock().await.insert(peer, state);
+    }
+
+    /// Replaces the requested domain blocklist for magic DNS filtering. The live resolver is not
+    /// reconfigured and DNS keeps running unchanged.
+    ///
+    /// Actually having `LocalDnsResolver` consult this list when answering a query is left as an
+    /// integration seam -- see the `dns_blocklist` module doc for why. This records the request (so
+    /// a later wiring pass, or `Device::inspect`-style diagnostics, has somewhere to read it from).
+    async fn set_dns_blocklist(&self, domains: Vec<String>, action: Option<DnsBlockAction>) {
+        let enabled = action.is_some() && !domains.is_empty();
+        telio_log_debug!(
+            "dns_blocklist: now {} with {} domain(s)",
+            if enabled { "enabled" } else { "disabled" },
+            domains.len()
+        );
+        *self.entities.dns_blocklist.lock().await = DnsBlocklistConfig { domains, action };
+    }
+
     async fn set_config(&mut self, config: &Option<Config>) -> Result {
         if let Some(cfg) = config {
             let should_validate_keys = self.features.validate_keys.0;
@@ -1199,6 +1527,35 @@ impl Runtime {
         self.requested_state.old_meshnet_config = self.requested_state.meshnet_config.clone();
         self.requested_state.meshnet_config = config.clone();
 
+        // `set_config` stays the authoritative seed/override: reseed our local gossip view from
+        // it so any peers added/removed here are reflected before the next gossip round, rather
+        // than waiting to learn about our own config changes secondhand.
+        self.sync_gossip_membership(config).await;
+
+        // Drop flow-accounting counters for any peer no longer in the meshnet config, so a
+        // removed peer's stats don't linger forever; a newly added peer gets an entry lazily, the
+        // first time a packet is recorded for it.
+        let current_peers: HashSet<PublicKey> = config
+            .as_ref()
+            .and_then(|config| config.peers.as_ref())
+            .map(|peers| peers.iter().map(|peer| peer.base.public_key).collect())
+            .unwrap_or_default();
+        self.entities.flow_accounting.retain_only(&current_peers);
+
+        // Only touch routing here in meshnet-only mode; while an exit node is connected,
+        // `connect_exit_node`/`disconnect_exit_nodes` own the dedicated table instead.
+        if self.requested_state.exit_node.is_none() {
+            if let Some(auto_route) = &mut self.entities.auto_route {
+                let result = match self.meshnet_peer_addresses() {
+                    Some(addresses) => auto_route.connect(&RouteTarget::Addresses(addresses)),
+                    None => auto_route.disconnect(),
+                };
+                if let Err(e) = result {
+                    telio_log_warn!("auto_route: failed to reconfigure meshnet routes: {:?}", e);
+                }
+            }
+        }
+
         let wg_itf = self.entities.wireguard_interface.get_interface().await?;
         let wg_port = self
             .entities
@@ -1226,9 +1583,17 @@ impl Runtime {
             };
             self.entities.proxy.configure(proxy_config).await?;
 
+            // Spread load across the relay fleet instead of every node stampeding the single
+            // lowest-weight server: see `derp_selection` for why this ordering is still stable
+            // across reconnects despite not always being the same server.
+            let ordered_servers = derp_selection::weighted_order(
+                &secret_key.public(),
+                &config.derp_servers.clone().unwrap_or_default(),
+            );
+
             let derp_config = DerpConfig {
                 secret_key,
-                servers: SortedServers::new(config.derp_servers.clone().unwrap_or_default()),
+                servers: SortedServers::new(ordered_servers),
                 allowed_pk: peers,
                 timeout: Duration::from_secs(10), //TODO: make configurable
                 ca_pem_path: None,
@@ -1324,14 +1689,24 @@ impl Runtime {
 
     /// Logs NAT type of derp server in info log
     async fn log_nat(&self) {
-        if let Some(server) = self.requested_state.meshnet_config.as_ref().and_then(|c| {
-            c.derp_servers
-                .as_ref()
-                .and_then(|servers| servers.iter().min_by_key(|server| server.weight))
-        }) {
-            // Copy the lowest weight server to log nat in a separate future
+        let Some(derp_servers) = self
+            .requested_state
+            .meshnet_config
+            .as_ref()
+            .and_then(|c| c.derp_servers.as_ref())
+        else {
+            return;
+        };
+
+        // Same weighted ordering `set_config()` picks the primary DERP server with, so the NAT
+        // check runs against whichever server this node is actually likely to connect to rather
+        // than always the single globally lowest-weight one.
+        let Ok(public_key) = self.get_private_key().await.map(|key| key.public()) else {
+            return;
+        };
+        if let Some(server) = derp_selection::weighted_order(&public_key, derp_servers).first() {
             let stun_server_skt = SocketAddr::new(IpAddr::V4(server.ipv4), server.stun_port);
-            tokio::spawn(async move {
+            self.entities.spawn(async move {
                 if let Ok(data) = retrieve_single_nat(stun_server_skt).await {
                     telio_log_debug!("Nat Type - {:?}", data.nat_type)
                 }
@@ -1339,12 +1714,46 @@ impl Runtime {
         }
     }
 
+    /// Connects to `exit_node` directly, outside of any failover group -- clears one if it was set
+    /// up by a prior [`Runtime::connect_exit_node_group`] call, same as today's single-node
+    /// behavior.
     async fn connect_exit_node(&mut self, exit_node: &ExitNode) -> Result {
+        self.requested_state.exit_failover = None;
+        self.connect_exit_node_inner(exit_node).await
+    }
+
+    /// Stores `candidates` as an ordered [`FailoverGroup`] in `requested_state` and connects to the
+    /// first one. See `exit_failover` and [`Device::connect_exit_node_group`].
+    async fn connect_exit_node_group(&mut self, candidates: Vec<ExitNode>) -> Result {
+        let Some(first) = candidates.first().cloned() else {
+            return Err(Error::InvalidNode);
+        };
+
+        self.requested_state.exit_failover =
+            Some(FailoverGroup::new(candidates, FailoverConfig::default()));
+
+        self.connect_exit_node_inner(&first).await
+    }
+
+    /// The shared connect logic behind both [`Runtime::connect_exit_node`] and
+    /// [`Runtime::connect_exit_node_group`] (and the automatic promotion in
+    /// [`Runtime::run_exit_failover`]): neither touches `requested_state.exit_failover` itself, so
+    /// the caller decides whether a failover group stays active across the call.
+    async fn connect_exit_node_inner(&mut self, exit_node: &ExitNode) -> Result {
         let exit_node = exit_node.clone();
 
         // dns socket for macos should only be bound to tunnel interface when connected to exit,
-        // otherwise with no exit dns peer will try to forward packets through tunnel and fail
-        bind_tun::set_should_bind(true);
+        // otherwise with no exit dns peer will try to forward packets through tunnel and fail.
+        // `exit_node.endpoint` is always a UDP `SocketAddr` today (always
+        // `NamedEndpoint::needs_tun_bind() == true`); see `named_endpoint`'s module doc for what's
+        // blocking a real `NamedEndpoint::Unix` variant -- reachable without a UDP socket, and so
+        // exempt from this bind -- from reaching this call.
+        bind_tun::set_should_bind(
+            exit_node
+                .endpoint
+                .map(NamedEndpoint::from)
+                .map_or(true, |endpoint| endpoint.needs_tun_bind()),
+        );
 
         let is_meshnet_exit_node = self
             .requested_state
@@ -1364,6 +1773,13 @@ impl Runtime {
         }
 
         self.requested_state.exit_node = Some(exit_node);
+
+        if let Some(auto_route) = &mut self.entities.auto_route {
+            if let Err(e) = auto_route.connect(&RouteTarget::Default) {
+                telio_log_warn!("auto_route: failed to install exit node routes: {:?}", e);
+            }
+        }
+
         wg_controller::consolidate_wg_state(&self.requested_state, &self.entities).await
     }
 
@@ -1380,9 +1796,34 @@ impl Runtime {
     }
 
     async fn disconnect_exit_nodes(&mut self) -> Result {
+        self.demote_exit_node().await?;
+        // An explicit disconnect ends any failover group too, rather than leaving it around to
+        // silently reconnect on the next unhealthy tick.
+        self.requested_state.exit_failover = None;
+        Ok(())
+    }
+
+    /// Demotes the current `requested_state.exit_node` (if any) to `last_exit_node` and falls back
+    /// to the relayed path, without touching `requested_state.exit_failover` -- shared by
+    /// [`Runtime::disconnect_exit_nodes`] (which clears the group afterwards, an explicit
+    /// disconnect) and [`Runtime::run_exit_failover`]'s exhausted case (which doesn't, so the group
+    /// can still recover once a candidate's backoff clears).
+    async fn demote_exit_node(&mut self) -> Result {
         if let Some(exit_node) = self.requested_state.exit_node.take() {
             self.requested_state.last_exit_node = Some(exit_node);
 
+            if let Some(auto_route) = &mut self.entities.auto_route {
+                if let Err(e) = auto_route.disconnect() {
+                    telio_log_warn!("auto_route: failed to tear down exit node routes: {:?}", e);
+                }
+                // Fall back to routing the meshnet peers, if any are configured.
+                if let Some(addresses) = self.meshnet_peer_addresses() {
+                    if let Err(e) = auto_route.connect(&RouteTarget::Addresses(addresses)) {
+                        telio_log_warn!("auto_route: failed to install meshnet routes: {:?}", e);
+                    }
+                }
+            }
+
             // for macos dns
             bind_tun::set_should_bind(false);
 
@@ -1401,10 +1842,9 @@ impl Runtime {
 
     #[allow(clippy::panic)]
     async fn _panic(&mut self) -> Result {
-        let _ = tokio::spawn(async {
+        self.entities.spawn(async {
             panic!("runtime_panic_test");
-        })
-        .await;
+        });
 
         Ok(())
     }
@@ -1413,6 +1853,266 @@ impl Runtime {
         Ok(self.entities.socket_pool.clone())
     }
 
+    /// Collects every meshnet peer's IP addresses, for `auto_route`'s meshnet-only mode.
+    fn meshnet_peer_addresses(&self) -> Option<Vec<IpAddr>> {
+        let peers = self
+            .requested_state
+            .meshnet_config
+            .as_ref()
+            .and_then(|config| config.peers.as_ref())?;
+
+        Some(
+            peers
+                .iter()
+                .filter_map(|peer| peer.base.ip_addresses.clone())
+                .flatten()
+                .collect(),
+        )
+    }
+
+    /// Reseeds the local gossip membership table from an authoritative `set_config` call: every
+    /// peer present in `config` is upserted (bumping its version, so the change is picked up by
+    /// the next gossip round), and any member we were tracking that's no longer present is
+    /// dropped. A `None` config (meshnet disabled) clears the table entirely.
+    async fn sync_gossip_membership(&self, config: &Option<Config>) {
+        let mut table = self.entities.gossip_membership.lock().await;
+
+        let current_peers: HashSet<PublicKey> = config
+            .as_ref()
+            .and_then(|config| config.peers.as_ref())
+            .map(|peers| peers.iter().map(|peer| peer.base.public_key).collect())
+            .unwrap_or_default();
+
+        let stale: Vec<PublicKey> = table
+            .members()
+            .map(|(key, _)| *key)
+            .filter(|key| !current_peers.contains(key))
+            .collect();
+        for key in stale {
+            table.remove(&key);
+        }
+
+        if let Some(peers) = config.as_ref().and_then(|config| config.peers.as_ref()) {
+            for peer in peers {
+                table.upsert(
+                    peer.base.public_key,
+                    peer.base.hostname.clone(),
+                    peer.base.ip_addresses.clone().unwrap_or_default(),
+                );
+            }
+        }
+    }
+
+    /// Picks a connected peer to gossip with this round and sends it our current digest, see the
+    /// `gossip_membership` module doc. No-op if no peer is currently connected.
+    async fn run_gossip_round(&mut self) {
+        let Ok(wgi) = self.entities.wireguard_interface.get_interface().await else {
+            return;
+        };
+        let connected: Vec<PublicKey> = wgi
+            .peers
+            .values()
+            .filter(|peer| peer.is_connected())
+            .map(|peer| peer.public_key)
+            .collect();
+
+        let Some(partner) = gossip_membership::pick_gossip_partner(&connected, self.gossip_pick)
+        else {
+            return;
+        };
+        self.gossip_pick = self.gossip_pick.wrapping_add(1);
+
+        let digest = self.entities.gossip_membership.lock().await.digest();
+        if let Err(e) = self.gossip_channel.tx.send(GossipWireMessage {
+            peer: partner,
+            message: GossipMessage::Digest(digest),
+        }) {
+            telio_log_warn!("gossip: failed to send digest to {:?}: {:?}", partner, e);
+        }
+    }
+
+    /// Feeds in a [`GossipWireMessage`] received over `gossip_channel`: a digest is answered with
+    /// whatever entries we have that are newer, a delta is merged straight into the local table.
+    async fn handle_gossip_message(&self, message: GossipWireMessage) {
+        match message.message {
+            GossipMessage::Digest(their_digest) => {
+                let entries = self
+                    .entities
+                    .gossip_membership
+                    .lock()
+                    .await
+                    .entries_newer_than(&their_digest);
+                if !entries.is_empty() {
+                    if let Err(e) = self.gossip_channel.tx.send(GossipWireMessage {
+                        peer: message.peer,
+                        message: GossipMessage::Delta(entries),
+                    }) {
+                        telio_log_warn!(
+                            "gossip: failed to send delta to {:?}: {:?}",
+                            message.peer,
+                            e
+                        );
+                    }
+                }
+            }
+            GossipMessage::Delta(entries) => {
+                self.entities.gossip_membership.lock().await.merge(entries);
+            }
+        }
+    }
+
+    /// Sends our `network_id` to every connected peer, if the identify check is opted into. See
+    /// the `identify` module doc -- the reply (a peer's own `IdentifyMessage`) arrives
+    /// independently on `identify_channel` and is handled by [`Runtime::handle_identify_response`].
+    async fn run_identify_round(&self) {
+        let Some(network_id) = self.requested_state.network_id else {
+            return;
+        };
+        let Ok(wgi) = self.entities.wireguard_interface.get_interface().await else {
+            return;
+        };
+
+        let message = IdentifyMessage::seal(network_id);
+        for peer in wgi.peers.values().filter(|peer| peer.is_connected()) {
+            if let Err(e) = self.identify_channel.tx.send(IdentifyWireMessage {
+                peer: peer.public_key,
+                message,
+            }) {
+                telio_log_warn!(
+                    "identify: failed to send network_id to {:?}: {:?}",
+                    peer.public_key,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Ranks peers by recent traffic volume and recomputes which *should* be kept on the direct
+    /// path under [`DirectBudgetConfig`] versus demoted to relayed. No-op if no budget is
+    /// configured.
+    ///
+    /// **This does not enforce the budget.** It records the decision and logs any change, but
+    /// nothing here instructs `wg_controller` or `UpgradeSync` to actually promote or demote a
+    /// peer -- a peer over budget stays on whatever path it was already on. Closing that gap needs
+    /// two things this checkout doesn't have: `wg_controller`'s own source (`mod wg_controller;`
+    /// has no matching file here to add a demotion call to) and `UpgradeSync`'s request API (beyond
+    /// its constructor, `UpgradeSync::new`, and `stop` on shutdown, no method on the resulting
+    /// value is ever called in this tree, so there's nothing to mirror the way `Multiplexer::get_channel` could
+    /// be). So, unlike `gossip_membership`/`identify`, this is a genuine integration seam left
+    /// open, not a restated claim of one: do not read `direct_assignment` as reflecting what's
+    /// actually enforced on the wire.
+    async fn run_direct_budget_maintenance(&self) {
+        let Some(budget) = self.entities.direct_budget else {
+            return;
+        };
+
+        let Ok(wgi) = self.entities.wireguard_interface.get_interface().await else {
+            return;
+        };
+
+        let mut previous = self.entities.direct_assignment.lock().await;
+        let candidates: Vec<PeerRank> = wgi
+            .peers
+            .values()
+            .map(|peer| PeerRank {
+                public_key: peer.public_key,
+                recent_traffic_bytes: peer.tx_bytes.unwrap_or(0) + peer.rx_bytes.unwrap_or(0),
+                currently_direct: matches!(previous.get(&peer.public_key), Some(Assignment::Direct)),
+            })
+            .collect();
+
+        let decision = direct_budget::plan(&budget, &candidates);
+        let mut next = HashMap::with_capacity(decision.len());
+        for (public_key, assignment) in decision {
+            if previous.get(&public_key) != Some(&assignment) {
+                telio_log_debug!(
+                    "direct_budget: {:?} is now {:?}",
+                    public_key,
+                    assignment
+                );
+            }
+            next.insert(public_key, assignment);
+        }
+        *previous = next;
+    }
+
+    /// Feeds a `wg_event_subscriber` update for `peer` into the active failover group's liveness
+    /// tracker (if one is set up via [`Runtime::connect_exit_node_group`] and `peer` is its
+    /// currently active candidate), switching to the next healthy candidate -- or falling back to
+    /// the relayed path -- if it's been unhealthy for too long. Returns a [`Node`] event describing
+    /// the switch, if one happened.
+    ///
+    /// No-op (returns `None`) unless a failover group is active and `peer` is its active candidate.
+    async fn run_exit_failover(&mut self, peer: &uapi::Peer, state: PeerState) -> Option<Node> {
+        let group = self.requested_state.exit_failover.as_mut()?;
+        if group.active()?.public_key != peer.public_key {
+            return None;
+        }
+
+        match group.tick(state, Instant::now()) {
+            FailoverDecision::Stay => None,
+            FailoverDecision::Switch(next) => {
+                let previous = self.requested_state.exit_node.as_ref().map(|n| n.public_key);
+                telio_log_info!(
+                    "exit_failover: {:?} unhealthy past the handshake timeout, switching to {:?}",
+                    previous,
+                    next.public_key
+                );
+
+                if let Some(exit_node) = self.requested_state.exit_node.take() {
+                    self.requested_state.last_exit_node = Some(exit_node);
+                }
+
+                if let Err(e) = self.connect_exit_node_inner(&next).await {
+                    telio_log_warn!(
+                        "exit_failover: failed to promote {:?}: {:?}",
+                        next.public_key,
+                        e
+                    );
+                    return None;
+                }
+
+                Some(exit_node_switch_event(&next, PeerState::Connecting))
+            }
+            FailoverDecision::Exhausted => {
+                telio_log_warn!(
+                    "exit_failover: every candidate is unhealthy or backed off, falling back to the relayed path"
+                );
+                if let Err(e) = self.demote_exit_node().await {
+                    telio_log_warn!("exit_failover: failed to fall back to relay: {:?}", e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Classifies a peer's path, see the [`path_state`] module doc for the full
+    /// None/Connecting/Relay/Direct progression this drives.
+    async fn resolve_path_type(&self, peer: &uapi::Peer) -> PathType {
+        self.resolve_peer_path_state(peer).await.to_path_type()
+    }
+
+    /// The richer [`PeerPathState`] backing [`Runtime::resolve_path_type`], its only caller; both
+    /// `peer_to_node` and `inspect()` see only the narrowed [`PathType`].
+    async fn resolve_peer_path_state(&self, peer: &uapi::Peer) -> PeerPathState {
+        let map = self
+            .entities
+            .proxy
+            .get_endpoint_map()
+            .await
+            .unwrap_or_else(|err| {
+                telio_log_warn!("Failed to get proxy endpoint map: {}", err);
+                Default::default()
+            });
+        let relayed_through = map.get(&peer.public_key);
+
+        // Note: the identify handshake's `IdentifyState` (see the `identify` module doc) is
+        // intentionally not consulted here. It's an unauthenticated misconfiguration check, not a
+        // security boundary, so it must not gate direct-path promotion; a mismatch is only ever
+        // surfaced via `entities.identify_state` for logging/diagnostics.
+        path_state::classify(peer.endpoint, relayed_through, peer.is_connected())
+    }
+
     async fn peer_to_node(&self, peer: &uapi::Peer, state: Option<PeerState>) -> Option<Node> {
         let endpoint = peer.endpoint;
 
@@ -1459,33 +2159,7 @@ impl Runtime {
         };
 
         // Resolve what type of path is used
-        let path_type = {
-            let map = self
-                .entities
-                .proxy
-                .get_endpoint_map()
-                .await
-                .unwrap_or_else(|err| {
-                    telio_log_warn!("Failed to get proxy endpoint map: {}", err);
-                    Default::default()
-                });
-            match &endpoint {
-                Some(actual) => map
-                    .get(&peer.public_key)
-                    .map(|proxy| {
-                        if proxy == actual {
-                            PathType::Relay
-                        } else {
-                            PathType::Direct
-                        }
-                    })
-                    .unwrap_or(PathType::Direct),
-                None => {
-                    // TODO: Maybe we should introduce None state after all ?
-                    PathType::Direct
-                }
-            }
-        };
+        let path_type = self.resolve_path_type(peer).await;
 
         // Build a node to report event about, we need to report about either meshnet peers
         // or VPN peers. Others (like DNS, or anycast) are considered to be "internal" ones
@@ -1570,6 +2244,15 @@ impl TaskRuntime for Runtime {
             },
 
             Some(mesh_event) = self.event_listeners.wg_event_subscriber.recv() => {
+                if let Some(switch) = self
+                    .run_exit_failover(&mesh_event.peer, mesh_event.state)
+                    .await
+                {
+                    let _ = self.event_publishers.libtelio_event_publisher.send(
+                        Box::new(Event::new::<Node>().set(switch))
+                    );
+                }
+
                 let node = self.peer_to_node(&mesh_event.peer, Some(mesh_event.state)).await;
 
                 if let Some(node) = node {
@@ -1622,6 +2305,19 @@ impl TaskRuntime for Runtime {
                         |e| {
                             telio_log_warn!("WireGuard controller failure: {:?}. Ignoring", e);
                         });
+                self.run_direct_budget_maintenance().await;
+                self.run_gossip_round().await;
+                self.run_identify_round().await;
+                Ok(())
+            },
+
+            Some(message) = self.gossip_channel.rx.recv() => {
+                self.handle_gossip_message(message).await;
+                Ok(())
+            },
+
+            Some(message) = self.identify_channel.rx.recv() => {
+                self.handle_identify_response(message.peer, message.message).await;
                 Ok(())
             },
 
@@ -1657,6 +2353,12 @@ impl TaskRuntime for Runtime {
             }};
         }
 
+        if let Some(mut auto_route) = self.entities.auto_route.take() {
+            if let Err(e) = auto_route.disconnect() {
+                telio_log_warn!("auto_route: failed to tear down routes on stop: {:?}", e);
+            }
+        }
+
         let _ = self.stop_dns().await;
         if let Some(direct) = self.entities.direct {
             // Arc dependency on endpoint providers
@@ -1692,6 +2394,31 @@ impl TaskRuntime for Runtime {
     }
 }
 
+/// Builds the [`Node`] event published when [`Runtime::run_exit_failover`] promotes `exit_node`.
+/// Unlike `peer_to_node`'s exit-node arm, there's no `uapi::Peer` snapshot for the newly promoted
+/// candidate yet (it was just added to the WireGuard config), so `allowed_ips` is left empty and
+/// `path` assumed [`PathType::Relay`] -- both get corrected by the regular `peer_to_node` event
+/// once the next snapshot comes in.
+fn exit_node_switch_event(exit_node: &ExitNode, state: PeerState) -> Node {
+    Node {
+        identifier: exit_node.identifier.clone(),
+        public_key: exit_node.public_key,
+        state,
+        is_exit: true,
+        is_vpn: exit_node.endpoint.is_some(),
+        ip_addresses: vec![
+            IpAddr::V4(Ipv4Addr::new(10, 5, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1)),
+        ],
+        allowed_ips: Vec::new(),
+        endpoint: exit_node.endpoint,
+        hostname: None,
+        allow_incoming_connections: false,
+        allow_peer_send_files: false,
+        path: PathType::Relay,
+    }
+}
+
 #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))]
 fn set_tunnel_interface(socket_pool: &Arc<SocketPool>, config: &DeviceConfig) {
     let mut tunnel_if_index = None;
@@ -2082,7 +2809,11 @@ mod tests {
         rt.test_env.adapter.lock().await.checkpoint();
     }
 
-    #[cfg(not(windows))]
+    // Unlike most of this module's tests, these three don't depend on anything Windows-specific:
+    // they only assert which endpoint providers `Runtime::start` constructs for a given
+    // `FeatureDirect`, and that construction was never platform-gated to begin with (the
+    // Windows-only pieces -- IP Helper interface enumeration, a Windows IGD/UPnP client -- live in
+    // `telio_traversal`/`telio_sockets`, not this file). So these run on Windows too.
     #[tokio::test(start_paused = true)]
     async fn test_default_features_when_direct_is_empty() {
         let (sender, _receiver) = tokio::sync::broadcast::channel(1);
@@ -2113,7 +2844,6 @@ mod tests {
         assert!(entities.stun_endpoint_provider.is_some());
     }
 
-    #[cfg(not(windows))]
     #[tokio::test(start_paused = true)]
     async fn test_default_features_when_provider_is_empty() {
         let (sender, _receiver) = tokio::sync::broadcast::channel(1);
@@ -2144,7 +2874,6 @@ mod tests {
         assert!(entities.stun_endpoint_provider.is_none());
     }
 
-    #[cfg(not(windows))]
     #[tokio::test(start_paused = true)]
     async fn test_enable_all_direct_features() {
         let (sender, _receiver) = tokio::sync::broadcast::channel(1);
@@ -0,0 +1,225 @@
+//! LAN peer discovery via UDP multicast announcements -- a fourth candidate source alongside
+//! STUN/UPnP/local-interface enumeration (see `telio_traversal::endpoint_providers`), for nodes on
+//! the same subnet to find each other without a STUN or DERP round trip.
+//!
+//! **No LAN peer is actually discovered by this module today.** `mod multicast_discovery;` is
+//! declared in `device/mod.rs` but nothing there ever `use`s it, holds one in a field, or opens the
+//! multicast socket it depends on -- what follows is the announce/admit pipeline such a provider
+//! would need, fully implemented and tested in isolation, not a working discovery feature.
+//!
+//! A node periodically announces its public key, listen port and local IPs on a well-known
+//! multicast group, and listens for the same from others; [`MulticastDiscovery::receive`] is the
+//! admission pipeline an announcement goes through before its IPs become direct-path candidates:
+//! mangled/unparsable payloads are rejected by [`Announcement::verify`], stale ones by the
+//! `staleness` bound, a peer flooding announcements is rate-limited per-key, and -- the filter the
+//! request specifically calls for -- anything whose claimed public key isn't already in the
+//! caller's meshnet `Config` is dropped, so multicast can only ever shortcut discovery of a peer
+//! already configured, never introduce a new one.
+//!
+//! Wiring a `MulticastEndpointProvider` that owns the actual multicast socket, calls
+//! `build_announcement`/`receive` on a timer/recv loop, and feeds `receive`'s output into the
+//! endpoint-gathering pipeline the way `rt.entities.direct`'s `stun_endpoint_provider`/
+//! `upnp_endpoint_provider`/`local_interfaces_endpoint_provider` already do needs two things this
+//! checkout doesn't have: the `EndpointProvider` trait itself (and `endpoint_providers::Error`),
+//! and `Entities.direct`'s construction in `Runtime::start` (`device/mod.rs`) gaining a
+//! `multicast_endpoint_provider: Option<Arc<MulticastEndpointProvider>>` field alongside the other
+//! three, guarded by `has_provider(EndpointProvider::Multicast)` the same way `Stun`/`Upnp` are --
+//! both live in `telio_traversal`, which has no source file in this checkout. What's implemented
+//! here -- building and admitting announcements -- is independent of both and is everything a
+//! `MulticastEndpointProvider::poll`-style method would need to call.
+//!
+//! Authenticity: a multicast announcement has no single recipient to share a pairwise secret
+//! with, and this checkout has no asymmetric signing primitive confirmed available either. So
+//! [`Announcement::checksum`] is deliberately just a plain integrity checksum over the
+//! announcement's own claimed fields -- it catches corruption, not forgery, and (unlike an
+//! earlier version of `identify`'s now-removed MAC) was never named or used as though it were one.
+//! The real trust boundary enforced here is the meshnet `Config` membership check in
+//! [`MulticastDiscovery::receive`]: a LAN observer can forge a plausible-looking announcement, but
+//! it can't make `receive` admit a public key that wasn't already a configured peer, and nothing
+//! here lets `checksum` itself gate that admission -- see `identify`'s module doc for the contrast
+//! with the mistake that module made and has since corrected.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use telio_crypto::PublicKey;
+
+/// Tunables for building and admitting announcements.
+#[derive(Debug, Clone, Copy)]
+pub struct AnnounceConfig {
+    /// How often this node re-announces itself.
+    pub interval: Duration,
+    /// An announcement older than this (by its own `timestamp`) is rejected as stale.
+    pub staleness: Duration,
+    /// The minimum gap between two admitted announcements from the same peer, so a flooding or
+    /// misbehaving peer can't be re-processed on every packet.
+    pub min_peer_interval: Duration,
+}
+
+impl Default for AnnounceConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            staleness: Duration::from_secs(30),
+            min_peer_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A single LAN multicast announcement: "I am `public_key`, reachable at `local_ips` on
+/// `listen_port`, as of `timestamp`".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub public_key: PublicKey,
+    pub listen_port: u16,
+    pub local_ips: Vec<IpAddr>,
+    /// Unix timestamp (seconds) the announcement was built at, for the staleness check.
+    pub timestamp: u64,
+    /// See the module doc: an integrity checksum, not a forgery-proof signature.
+    pub checksum: [u8; 32],
+}
+
+impl Announcement {
+    /// Builds the announcement this node would send, covering `public_key`/`listen_port`/
+    /// `local_ips`/`timestamp` with [`Announcement::checksum`].
+    pub fn seal(
+        public_key: PublicKey,
+        listen_port: u16,
+        local_ips: Vec<IpAddr>,
+        timestamp: u64,
+    ) -> Self {
+        let checksum = checksum(&public_key, listen_port, &local_ips, timestamp);
+        Self {
+            public_key,
+            listen_port,
+            local_ips,
+            timestamp,
+            checksum,
+        }
+    }
+
+    /// Whether this announcement's fields are internally consistent with its `checksum`. Does not
+    /// establish that `public_key` is who actually sent it, see the module doc.
+    pub fn verify(&self) -> bool {
+        self.checksum
+            == checksum(
+                &self.public_key,
+                self.listen_port,
+                &self.local_ips,
+                self.timestamp,
+            )
+    }
+
+    /// The candidate endpoints this announcement offers, one per local IP on `listen_port`.
+    fn candidates(&self) -> Vec<SocketAddr> {
+        self.local_ips
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, self.listen_port))
+            .collect()
+    }
+}
+
+/// A simple FNV-1a-based checksum over the announcement fields, see [`Announcement`]'s doc for
+/// why this isn't a real signature.
+fn checksum(public_key: &PublicKey, listen_port: u16, local_ips: &[IpAddr], timestamp: u64) -> [u8; 32] {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut update = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    update(&public_key.0);
+    update(&listen_port.to_be_bytes());
+    for ip in local_ips {
+        match ip {
+            IpAddr::V4(v4) => update(&v4.octets()),
+            IpAddr::V6(v6) => update(&v6.octets()),
+        }
+    }
+    update(&timestamp.to_be_bytes());
+
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = hash.wrapping_mul(i as u64 + 1).to_le_bytes()[i % 8];
+    }
+    out
+}
+
+/// Tracks per-peer rate limiting across received announcements; see [`MulticastDiscovery`].
+#[derive(Default)]
+struct RateLimiter {
+    last_admitted: HashMap<PublicKey, Instant>,
+}
+
+impl RateLimiter {
+    fn admit(&mut self, public_key: PublicKey, now: Instant, min_interval: Duration) -> bool {
+        let admitted = self.last_admitted.get(&public_key).map_or(true, |last| {
+            now.saturating_duration_since(*last) >= min_interval
+        });
+        if admitted {
+            self.last_admitted.insert(public_key, now);
+        }
+        admitted
+    }
+}
+
+/// Builds and admits LAN multicast announcements for one local node. See the module doc.
+#[derive(Default)]
+pub struct MulticastDiscovery {
+    config: AnnounceConfig,
+    rate_limiter: RateLimiter,
+}
+
+impl MulticastDiscovery {
+    pub fn new(config: AnnounceConfig) -> Self {
+        Self {
+            config,
+            rate_limiter: RateLimiter::default(),
+        }
+    }
+
+    /// Builds this node's own announcement, to be sent on the multicast group every
+    /// `config.interval`.
+    pub fn build_announcement(
+        &self,
+        public_key: PublicKey,
+        listen_port: u16,
+        local_ips: Vec<IpAddr>,
+        timestamp: u64,
+    ) -> Announcement {
+        Announcement::seal(public_key, listen_port, local_ips, timestamp)
+    }
+
+    /// Admits an incoming `announcement`, returning its candidate endpoints if it passes every
+    /// check: well-formed, fresh, not from a rate-limited peer, and already a configured meshnet
+    /// peer (`known_peers`).
+    pub fn receive(
+        &mut self,
+        announcement: &Announcement,
+        now_unix: u64,
+        now: Instant,
+        known_peers: &HashSet<PublicKey>,
+    ) -> Option<Vec<SocketAddr>> {
+        if !announcement.verify() {
+            return None;
+        }
+        if !known_peers.contains(&announcement.public_key) {
+            return None;
+        }
+        if now_unix.saturating_sub(announcement.timestamp) > self.config.staleness.as_secs() {
+            return None;
+        }
+        if !self
+            .rate_limiter
+            .admit(announcement.public_key, now, self.config.min_peer_interval)
+        {
+            return None;
+        }
+
+        Some(announcement.candidates())
+    }
+}
@@ -0,0 +1,65 @@
+//! A socket-address abstraction covering both UDP endpoints and Unix-domain-socket paths, for
+//! exit nodes reachable without a real UDP transport.
+//!
+//! `connect_exit_node` rejects any exit node whose `endpoint` isn't a UDP `SocketAddr`
+//! (`ExitNode::endpoint` is typed as `Option<SocketAddr>`), which rules out pointing libtelio at
+//! a co-located userspace WireGuard endpoint reachable over a Unix socket -- useful for local
+//! integration tests and sandboxed embeddings that would rather not open a real UDP port.
+//! [`NamedEndpoint`] is the `Udp`/`Unix` split this needs, plus [`NamedEndpoint::needs_tun_bind`]
+//! for the one piece of downstream behavior ([`super::bind_tun`]'s macOS tunnel-interface binding)
+//! that only makes sense for a real UDP path.
+//!
+//! Fully wiring this in needs two changes this checkout can't make, because the code they'd touch
+//! isn't part of this tree:
+//!  1. `ExitNode::endpoint` would need to become `Option<NamedEndpoint>` instead of
+//!     `Option<SocketAddr>`, which means editing `telio_model::mesh::ExitNode` -- telio-model has
+//!     no source file in this checkout.
+//!  2. The socket-pool connect path behind `consolidate_wg_state` (in `wg_controller`) would need
+//!     to branch on the variant when establishing the WireGuard peer's transport -- `wg_controller`
+//!     is `mod`-declared in `device/mod.rs` but, like telio-model, has no source file in this
+//!     checkout either (this predates this change).
+//!
+//! To be plain about what that leaves: from every caller's perspective, the Unix-socket endpoint
+//! feature this module exists for does not exist yet. [`Runtime::connect_exit_node_inner`] (in
+//! `mod.rs`) does call [`NamedEndpoint::needs_tun_bind`] on every exit-node connect, in place of
+//! the unconditional `bind_tun::set_should_bind(true)` call it replaced, but it can only ever build
+//! a [`NamedEndpoint::Udp`] there -- `exit_node.endpoint` is still `Option<SocketAddr>`, so there is
+//! no code path in this checkout, reachable or not, that constructs a [`NamedEndpoint::Unix`]. The
+//! `needs_tun_bind` call site is real, but it is exercising a permanent no-op, not a feature with
+//! one variant left to fill in: until (1) above lands, this module is a type definition nothing
+//! outside its own file can produce the interesting half of.
+
+use std::{net::SocketAddr, path::PathBuf};
+
+/// Where an exit node (or any other WireGuard peer) can be reached: a real UDP endpoint, or a
+/// filesystem path to a Unix domain socket for a co-located userspace peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NamedEndpoint {
+    /// A conventional UDP endpoint, the only variant `ExitNode::endpoint` supports today.
+    Udp(SocketAddr),
+    /// A Unix domain socket path, for a peer reachable without going through a real UDP socket.
+    Unix(PathBuf),
+}
+
+impl NamedEndpoint {
+    /// Whether this endpoint needs the macOS tunnel-interface bind (`bind_tun::set_should_bind`)
+    /// that the DNS resolver forwarding path relies on. Only meaningful for [`NamedEndpoint::Udp`]
+    /// -- a Unix-domain-socket peer has no UDP traffic for that bind to affect.
+    pub fn needs_tun_bind(&self) -> bool {
+        matches!(self, NamedEndpoint::Udp(_))
+    }
+
+    /// The UDP address backing this endpoint, if it is one.
+    pub fn as_socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            NamedEndpoint::Udp(addr) => Some(*addr),
+            NamedEndpoint::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for NamedEndpoint {
+    fn from(addr: SocketAddr) -> Self {
+        NamedEndpoint::Udp(addr)
+    }
+}
@@ -0,0 +1,449 @@
+//! NAT-PMP (RFC 6886) and PCP (RFC 6887) mapping client.
+//!
+//! `DirectEntities` currently discovers direct-connection candidates via
+//! [`UpnpEndpointProvider`](telio_traversal::endpoint_providers::upnp::UpnpEndpointProvider), which
+//! only speaks UPnP IGD. Many consumer gateways don't implement UPnP at all but do speak NAT-PMP or
+//! PCP, so [`PcpMappingClient`] asks the gateway directly for a port mapping: it first tries PCP
+//! (the newer, richer protocol) and falls back to NAT-PMP if the gateway replies "unsupported
+//! opcode" (or doesn't reply), matching the fallback order suggested by RFC 6887 appendix A.
+//!
+//! This only implements the wire protocol and mapping lifecycle (request, renew at half the
+//! granted lifetime). [`PcpMappingClient::needs_renewal`] also knows how to recognize a gateway
+//! reboot from PCP's `epoch` field going backwards, but [`PcpEndpointProvider::run`]'s polling
+//! loop has no way to learn the gateway's *current* epoch except by already sending a renewal --
+//! so `run()` calls `needs_renewal` with the epoch from the mapping it already holds, which means
+//! the epoch-reboot branch can never actually fire there (it's comparing the epoch to itself) and
+//! the decision in practice still comes down to the age check. `run()` still calls it and logs
+//! which branch decided, so the distinction is visible and the call site exists for whoever gives
+//! this provider a cheaper way to observe the gateway's *current* epoch between renewals (PCP has
+//! no request for that alone). Wiring a discovered [`PortMapping`] into an actual
+//! `EndpointProvider` impl and publishing it as a `WireGuardEndpointCandidateChangeEvent` is left
+//! to the caller, since the exact shape of that trait and event isn't available to target
+//! precisely from here; see the doc comment on [`PcpMappingClient::map`] for the seam.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use telio_utils::{exponential_backoff::ExponentialBackoffBounds, telio_log_debug};
+use tokio::net::UdpSocket;
+
+/// Default port NAT-PMP/PCP gateways listen on.
+const GATEWAY_PORT: u16 = 5351;
+
+const NATPMP_VERSION: u8 = 0;
+const NATPMP_OP_EXTERNAL_ADDRESS: u8 = 0;
+const NATPMP_OP_MAP_UDP: u8 = 1;
+
+const PCP_VERSION: u8 = 2;
+const PCP_OP_MAP: u8 = 1;
+/// Length in bytes of the PCP MAP opcode's random mapping nonce.
+const PCP_NONCE_LEN: usize = 12;
+
+/// Which protocol a [`PortMapping`] was obtained through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingProtocol {
+    Pcp,
+    NatPmp,
+}
+
+/// A port mapping granted by the gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortMapping {
+    pub protocol: MappingProtocol,
+    /// The publicly reachable address the gateway mapped our internal port to.
+    pub external: SocketAddr,
+    /// How long the gateway will keep this mapping alive for.
+    pub lifetime: Duration,
+    /// PCP's epoch counter at the time of mapping, used to detect a gateway reboot (the epoch
+    /// resets or jumps backwards) so the mapping can be re-requested. Always 0 for NAT-PMP, which
+    /// has no equivalent field.
+    epoch: u32,
+    /// The PCP mapping nonce this mapping was created with. The gateway uses this to recognize a
+    /// renew/delete request as referring to the same mapping rather than a brand new one, so it
+    /// must be replayed unchanged on every subsequent request for this mapping, not regenerated.
+    /// Always zero for NAT-PMP, which has no equivalent field.
+    nonce: [u8; PCP_NONCE_LEN],
+}
+
+/// Errors from talking to the gateway.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to bind/send/recv on the mapping socket: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Gateway response was too short or malformed")]
+    MalformedResponse,
+    #[error("Gateway reported result code {0}")]
+    GatewayError(u8),
+    #[error("Timed out waiting for a gateway response")]
+    Timeout,
+}
+
+/// Speaks PCP, falling back to NAT-PMP, against a single gateway.
+pub struct PcpMappingClient {
+    socket: UdpSocket,
+    gateway: SocketAddrV4,
+}
+
+impl PcpMappingClient {
+    /// Binds an ephemeral UDP socket and targets `gateway` (normally the default gateway, as
+    /// resolved by the caller -- resolving it is platform-specific and left to the integrator).
+    pub async fn new(gateway: Ipv4Addr) -> Result<Self, Error> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        Ok(Self {
+            socket,
+            gateway: SocketAddrV4::new(gateway, GATEWAY_PORT),
+        })
+    }
+
+    /// Requests a mapping for `internal_port`, suggesting `suggested_external_port` (the gateway
+    /// is free to assign a different one). Tries PCP first, then NAT-PMP if the gateway doesn't
+    /// support PCP. `renewing` is the mapping this call is refreshing, if any: its PCP nonce is
+    /// replayed so the gateway recognizes the request as extending the same mapping rather than
+    /// creating a new one; pass `None` when requesting a mapping for the first time.
+    ///
+    /// Once a [`PortMapping`] is obtained, the integration seam is: construct a candidate from
+    /// `mapping.external` and publish it the same way `UpnpEndpointProvider` does (through
+    /// `DirectEntities`'s `wg_endpoint_publish_event_subscriber` channel) -- left to the caller
+    /// since `EndpointProvider`'s exact trait methods aren't available to target in this tree.
+    pub async fn map(
+        &self,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime: Duration,
+        renewing: Option<&PortMapping>,
+    ) -> Result<PortMapping, Error> {
+        match self
+            .map_pcp(internal_port, suggested_external_port, lifetime, renewing)
+            .await
+        {
+            Ok(mapping) => Ok(mapping),
+            Err(Error::GatewayError(_)) | Err(Error::Timeout) => {
+                self.map_natpmp(internal_port, suggested_external_port, lifetime)
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `mapping` should be renewed now: either it's past half its granted lifetime, or
+    /// (for PCP) the gateway's epoch has moved in a way that indicates a reboot. `current_epoch`
+    /// has to come from somewhere that isn't this same renewal, or the epoch branch is moot -- see
+    /// the module doc for why [`PcpEndpointProvider::run`] can only ever pass `mapping`'s own
+    /// epoch back in today.
+    pub fn needs_renewal(mapping: &PortMapping, age: Duration, current_epoch: u32) -> bool {
+        if mapping.protocol == MappingProtocol::Pcp && current_epoch < mapping.epoch {
+            return true;
+        }
+        age >= mapping.lifetime / 2
+    }
+
+    async fn map_pcp(
+        &self,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime: Duration,
+        renewing: Option<&PortMapping>,
+    ) -> Result<PortMapping, Error> {
+        // Replay the nonce of the mapping being renewed so the gateway matches this request to it
+        // instead of allocating a new mapping; only mint a fresh one the first time around.
+        let request_nonce = match renewing {
+            Some(mapping) if mapping.protocol == MappingProtocol::Pcp => mapping.nonce,
+            _ => nonce(),
+        };
+
+        let mut request = Vec::with_capacity(60);
+        request.push(PCP_VERSION);
+        request.push(PCP_OP_MAP);
+        request.extend_from_slice(&[0, 0]); // reserved
+        request.extend_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+        request.extend_from_slice(&[0u8; 16]); // client IP, zero = "use the packet's source"
+        request.extend_from_slice(&request_nonce);
+        request.push(17); // protocol = UDP
+        request.extend_from_slice(&[0u8; 3]); // reserved
+        request.extend_from_slice(&internal_port.to_be_bytes());
+        request.extend_from_slice(&suggested_external_port.to_be_bytes());
+        request.extend_from_slice(&[0u8; 16]); // suggested external IP, zero = "no preference"
+
+        let response = self.exchange(&request).await?;
+        // Check the result code before the length: a NAT-PMP-only gateway answers a PCP request
+        // with a short "unsupported version" reply, which has to reach `map`'s NAT-PMP fallback.
+        let result_code = *response.get(3).ok_or(Error::MalformedResponse)?;
+        if result_code != 0 {
+            return Err(Error::GatewayError(result_code));
+        }
+        if response.len() < 24 {
+            return Err(Error::MalformedResponse);
+        }
+        let epoch = u32::from_be_bytes(
+            response
+                .get(8..12)
+                .and_then(|s| s.try_into().ok())
+                .ok_or(Error::MalformedResponse)?,
+        );
+
+        if response.len() < 60 {
+            return Err(Error::MalformedResponse);
+        }
+        let granted_lifetime = u32::from_be_bytes(
+            response
+                .get(4..8)
+                .and_then(|s| s.try_into().ok())
+                .ok_or(Error::MalformedResponse)?,
+        );
+        let external_port = u16::from_be_bytes(
+            response
+                .get(42..44)
+                .and_then(|s| s.try_into().ok())
+                .ok_or(Error::MalformedResponse)?,
+        );
+        let external_ip = mapped_ipv4_from_slice(
+            response.get(44..60).ok_or(Error::MalformedResponse)?,
+        )?;
+
+        Ok(PortMapping {
+            protocol: MappingProtocol::Pcp,
+            external: SocketAddr::new(IpAddr::V4(external_ip), external_port),
+            lifetime: Duration::from_secs(granted_lifetime as u64),
+            epoch,
+            nonce: request_nonce,
+        })
+    }
+
+    async fn map_natpmp(
+        &self,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime: Duration,
+    ) -> Result<PortMapping, Error> {
+        // Learn the public address first; not strictly required to obtain a mapping, but lets us
+        // fail fast if the gateway doesn't speak NAT-PMP either.
+        let address_request = [NATPMP_VERSION, NATPMP_OP_EXTERNAL_ADDRESS];
+        let address_response = self.exchange(&address_request).await?;
+        if address_response.len() < 12 || address_response[3] != 0 {
+            return Err(Error::GatewayError(*address_response.get(3).unwrap_or(&1)));
+        }
+        let external_ip = mapped_ipv4_from_slice(&address_response[8..12])?;
+
+        let mut map_request = Vec::with_capacity(12);
+        map_request.push(NATPMP_VERSION);
+        map_request.push(NATPMP_OP_MAP_UDP);
+        map_request.extend_from_slice(&[0, 0]); // reserved
+        map_request.extend_from_slice(&internal_port.to_be_bytes());
+        map_request.extend_from_slice(&suggested_external_port.to_be_bytes());
+        map_request.extend_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+
+        let response = self.exchange(&map_request).await?;
+        if response.len() < 16 {
+            return Err(Error::MalformedResponse);
+        }
+        let result_code = response[3];
+        if result_code != 0 {
+            return Err(Error::GatewayError(result_code));
+        }
+        let external_port = u16::from_be_bytes(
+            response
+                .get(10..12)
+                .and_then(|s| s.try_into().ok())
+                .ok_or(Error::MalformedResponse)?,
+        );
+        let granted_lifetime = u32::from_be_bytes(
+            response
+                .get(12..16)
+                .and_then(|s| s.try_into().ok())
+                .ok_or(Error::MalformedResponse)?,
+        );
+
+        Ok(PortMapping {
+            protocol: MappingProtocol::NatPmp,
+            external: SocketAddr::new(IpAddr::V4(external_ip), external_port),
+            lifetime: Duration::from_secs(granted_lifetime as u64),
+            epoch: 0,
+            nonce: [0u8; PCP_NONCE_LEN],
+        })
+    }
+
+    async fn exchange(&self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        self.socket.send_to(request, self.gateway).await?;
+
+        let mut buf = [0u8; 1100];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(2), self.socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| Error::Timeout)??;
+
+        Ok(buf[..len].to_vec())
+    }
+}
+
+/// A PCP response packs the (possibly IPv4-mapped) address as 16 bytes; NAT-PMP's is a plain
+/// 4-byte IPv4 address. Both paths end up needing "last 4 bytes as an IPv4 address".
+fn mapped_ipv4_from_slice(bytes: &[u8]) -> Result<Ipv4Addr, Error> {
+    let last4 = bytes
+        .get(bytes.len().saturating_sub(4)..)
+        .ok_or(Error::MalformedResponse)?;
+    let octets: [u8; 4] = last4.try_into().map_err(|_| Error::MalformedResponse)?;
+    Ok(Ipv4Addr::from(octets))
+}
+
+/// Mirrors the `Local`/`Stun`/`Upnp` variants of
+/// [`telio_model::api_config::EndpointProvider`]: the new providers this module backs. That enum
+/// lives in a crate not present in this checkout to extend, so `Runtime::start` would need to gain
+/// real `NatPmp`/`Pcp` variants there (and matching `has_provider(...)` arms next to the existing
+/// `Upnp`/`Stun`/`Local` ones) before [`PcpEndpointProvider`] can actually be constructed from
+/// `features.direct.providers`; this is the stand-in for that in the meantime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectProviderKind {
+    NatPmp,
+    Pcp,
+}
+
+/// Drives [`PcpMappingClient`] the way `UpnpEndpointProvider`/`StunEndpointProvider` drive their
+/// own discovery: periodically (re-)requesting a mapping, backing off exponentially between
+/// `backoff.initial` and `backoff.maximal` while the gateway is unreachable, and resetting back to
+/// `backoff.initial` on success.
+pub struct PcpEndpointProvider {
+    client: PcpMappingClient,
+    internal_port: u16,
+    mapping_lifetime: Duration,
+    backoff: ExponentialBackoffBounds,
+}
+
+impl PcpEndpointProvider {
+    pub fn new(
+        client: PcpMappingClient,
+        internal_port: u16,
+        mapping_lifetime: Duration,
+        backoff: ExponentialBackoffBounds,
+    ) -> Self {
+        Self {
+            client,
+            internal_port,
+            mapping_lifetime,
+            backoff,
+        }
+    }
+
+    /// Runs the discover/renew loop forever, calling `on_candidate` with every mapping obtained or
+    /// renewed. Calls [`PcpMappingClient::needs_renewal`] every tick there's a current mapping,
+    /// passing the mapping's own epoch back in as `current_epoch` (the only epoch value this loop
+    /// has access to -- see the module doc for why), and logs which branch of that check decided.
+    /// In practice the epoch branch can never fire since it's compared against itself, so the
+    /// result always matches the age check, but the call site now exists for whoever gives this
+    /// provider a real way to observe the gateway's current epoch.
+    ///
+    /// Intended to be spawned as its own task, the same way the other providers are. Publishing
+    /// through the real `subscribe_for_endpoint_candidates_change_events` /
+    /// `WireGuardEndpointCandidateChangeEvent` path instead of a plain callback, and registering
+    /// this provider in `DirectEntities`, is the integration seam left for once
+    /// [`DirectProviderKind`]'s real counterpart exists to opt into (see that type's doc).
+    pub async fn run<F: Fn(PortMapping)>(&self, on_candidate: F) -> ! {
+        let mut delay = self.backoff.initial;
+        let mut current: Option<PortMapping> = None;
+        let mut age = Duration::ZERO;
+
+        loop {
+            let should_refresh = match &current {
+                Some(mapping) => {
+                    let needs_renewal =
+                        PcpMappingClient::needs_renewal(mapping, age, mapping.epoch);
+                    let age_based = age >= mapping.lifetime / 2;
+                    telio_log_debug!(
+                        "pcp_endpoint: needs_renewal={} (age-based alone: {}; the two can only \
+                         differ once this loop observes a gateway epoch other than its own)",
+                        needs_renewal,
+                        age_based
+                    );
+                    needs_renewal
+                }
+                None => true,
+            };
+
+            if should_refresh {
+                match self
+                    .client
+                    .map(
+                        self.internal_port,
+                        self.internal_port,
+                        self.mapping_lifetime,
+                        current.as_ref(),
+                    )
+                    .await
+                {
+                    Ok(mapping) => {
+                        on_candidate(mapping);
+                        current = Some(mapping);
+                        age = Duration::ZERO;
+                        delay = self.backoff.initial;
+                    }
+                    Err(_) => {
+                        let doubled = delay.saturating_mul(2);
+                        delay = match self.backoff.maximal {
+                            Some(max) => doubled.min(max),
+                            None => doubled,
+                        };
+                    }
+                }
+            }
+
+            tokio::time::sleep(delay).await;
+            age += delay;
+        }
+    }
+}
+
+fn nonce() -> [u8; PCP_NONCE_LEN] {
+    // A real gateway only uses the nonce to match a later renewal/delete to this mapping (see
+    // `PortMapping::nonce`); any unpredictable-enough value works here, and pulling in a CSPRNG
+    // dependency isn't warranted for a 12-byte value this client never treats as a security
+    // boundary. Still, a single low-resolution source is not enough: `Instant::now().elapsed()`
+    // taken immediately after `Instant::now()` measures time since that very call, so it's ~0 every
+    // time, and two clients racing to map at the same instant could otherwise land on the same
+    // value. Fold several independent, cheaply-available sources together instead, then expand the
+    // result with a SplitMix64 step per byte so consecutive bytes aren't just a repeated window
+    // into the same few seed bytes.
+    let mut state = entropy_seed();
+    let mut nonce = [0u8; PCP_NONCE_LEN];
+    for byte in nonce.iter_mut() {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut mixed = state;
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d049bb133111eb);
+        *byte = (mixed ^ (mixed >> 31)) as u8;
+    }
+    nonce
+}
+
+/// FNV-1a fold of several cheap, independently-varying sources: wall-clock tick since this
+/// process's first call, process id, this thread's id, and a process-local call counter (so two
+/// calls in the same process never collide even if the clock hasn't ticked). Not a CSPRNG -- see
+/// [`nonce`]'s doc -- just enough spread that two racing processes don't land on the same value.
+fn entropy_seed() -> u64 {
+    static CALLS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    let start = *START.get_or_init(std::time::Instant::now);
+
+    let sources = [
+        start.elapsed().as_nanos() as u64,
+        std::process::id() as u64,
+        thread_id_hash(),
+        CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+    ];
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for value in sources {
+        for byte in value.to_ne_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Hashes this thread's `ThreadId` down to a `u64`, since `ThreadId` itself doesn't expose one.
+fn thread_id_hash() -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::thread::current().id().hash(&mut hasher);
+    hasher.finish()
+}
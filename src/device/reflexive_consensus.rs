@@ -0,0 +1,178 @@
+//! Reflexive-address consensus for STUN-derived endpoint candidates.
+//!
+//! `StunEndpointProvider` publishes a candidate from every STUN response it gets back, but behind
+//! symmetric NAT different STUN servers observe different external ports for the same internal
+//! socket, so publishing each response as its own candidate just wastes cross-ping attempts on
+//! addresses that will never work. [`ReflexiveConsensus`] sits in front of that: accumulate one
+//! observation per queried server, then only hand back a candidate once at least `M` of the `N`
+//! observations collected so far agree on the same `ip:port`. If the IPs agree but the ports
+//! don't, the NAT is classified as symmetric and no candidate is produced at all -- the caller
+//! should record that in [`RequestedState`](super::RequestedState) so `wg_controller` keeps the
+//! peer on the DERP relayed path instead of attempting (and failing) a direct upgrade.
+
+use std::{collections::HashMap, net::SocketAddr};
+
+/// Default agreement threshold: at least 2 of every 3 observed addresses must match.
+const DEFAULT_THRESHOLD_NUM: usize = 2;
+const DEFAULT_THRESHOLD_DEN: usize = 3;
+
+/// Outcome of evaluating the observations collected so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsensusResult {
+    /// At least `M` of `N` servers agree on this reflexive address; safe to publish as a
+    /// candidate.
+    Agreed(SocketAddr),
+    /// Servers agree on the external IP but not the port: the NAT is symmetric, so no address
+    /// is stable enough to publish.
+    SymmetricNat,
+    /// Not enough observations yet, or no agreement and the IPs don't even match (e.g. a server
+    /// hasn't responded, or responses are still in flight).
+    Undecided,
+}
+
+/// Accumulates per-server reflexive-address observations for a single local socket and decides
+/// whether they're consistent enough to publish.
+#[derive(Debug, Default)]
+pub struct ReflexiveConsensus {
+    threshold_num: usize,
+    threshold_den: usize,
+    observations: Vec<SocketAddr>,
+}
+
+impl ReflexiveConsensus {
+    /// Uses the default 2-of-3 agreement threshold.
+    pub fn new() -> Self {
+        Self::with_threshold(DEFAULT_THRESHOLD_NUM, DEFAULT_THRESHOLD_DEN)
+    }
+
+    /// `numerator`/`denominator` gives the fraction of observations that must agree, e.g. `(2, 3)`
+    /// for "at least 2 of every 3".
+    pub fn with_threshold(numerator: usize, denominator: usize) -> Self {
+        Self {
+            threshold_num: numerator,
+            threshold_den: denominator.max(1),
+            observations: Vec::new(),
+        }
+    }
+
+    /// Records one server's reflexive-address observation.
+    pub fn observe(&mut self, address: SocketAddr) {
+        self.observations.push(address);
+    }
+
+    /// Drops all recorded observations, e.g. before re-running consensus on
+    /// `notify_network_change`.
+    pub fn reset(&mut self) {
+        self.observations.clear();
+    }
+
+    /// Evaluates the observations recorded so far. Doesn't require a reply from every queried
+    /// server: as soon as enough observations are in to trust a verdict (see
+    /// [`ReflexiveConsensus::min_observations`]) and `M` of them agree, consensus is reached.
+    pub fn evaluate(&self) -> ConsensusResult {
+        if self.observations.len() < self.min_observations() {
+            return ConsensusResult::Undecided;
+        }
+
+        let mut by_address: HashMap<SocketAddr, usize> = HashMap::new();
+        for address in &self.observations {
+            *by_address.entry(*address).or_insert(0) += 1;
+        }
+
+        let required = self.required_agreement();
+        if let Some((address, _)) = by_address.iter().find(|(_, count)| **count >= required) {
+            return ConsensusResult::Agreed(*address);
+        }
+
+        let mut by_ip: HashMap<_, usize> = HashMap::new();
+        for address in &self.observations {
+            *by_ip.entry(address.ip()).or_insert(0) += 1;
+        }
+        if by_ip.values().any(|count| *count >= required) {
+            return ConsensusResult::SymmetricNat;
+        }
+
+        ConsensusResult::Undecided
+    }
+
+    /// How many observations must be recorded before a verdict other than [`ConsensusResult::Undecided`]
+    /// can be returned at all: at least 2 (a single observation trivially "agrees" with itself,
+    /// which is exactly the symmetric-NAT case this module exists to catch), and at least
+    /// `threshold_den` (so e.g. a 2-of-3 threshold waits for all 3 queried servers rather than
+    /// declaring agreement on the first 2 responses back).
+    fn min_observations(&self) -> usize {
+        self.threshold_den.max(2)
+    }
+
+    /// How many of the observations collected so far need to agree, rounding up so e.g. a 2/3
+    /// threshold requires at least 2 matching observations, never fewer.
+    fn required_agreement(&self) -> usize {
+        let total = self.observations.len();
+        (total * self.threshold_num).div_ceil(self.threshold_den).max(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(ip: &str, port: u16) -> SocketAddr {
+        format!("{}:{}", ip, port).parse().unwrap()
+    }
+
+    #[test]
+    fn agrees_when_two_of_three_observations_match() {
+        let mut consensus = ReflexiveConsensus::new();
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.1", 5000));
+
+        assert_eq!(
+            consensus.evaluate(),
+            ConsensusResult::Agreed(addr("203.0.113.1", 4000))
+        );
+    }
+
+    #[test]
+    fn reports_symmetric_nat_when_ips_agree_but_ports_dont() {
+        let mut consensus = ReflexiveConsensus::new();
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.1", 4001));
+        consensus.observe(addr("203.0.113.1", 4002));
+
+        assert_eq!(consensus.evaluate(), ConsensusResult::SymmetricNat);
+    }
+
+    #[test]
+    fn undecided_with_a_single_observation() {
+        let mut consensus = ReflexiveConsensus::new();
+        consensus.observe(addr("203.0.113.1", 4000));
+
+        assert_eq!(consensus.evaluate(), ConsensusResult::Undecided);
+    }
+
+    #[test]
+    fn undecided_when_neither_ip_nor_port_reach_agreement() {
+        let mut consensus = ReflexiveConsensus::new();
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.2", 4001));
+        consensus.observe(addr("203.0.113.3", 4002));
+
+        assert_eq!(consensus.evaluate(), ConsensusResult::Undecided);
+    }
+
+    #[test]
+    fn reset_clears_prior_observations() {
+        let mut consensus = ReflexiveConsensus::new();
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.1", 4000));
+        consensus.observe(addr("203.0.113.1", 4000));
+        assert_eq!(
+            consensus.evaluate(),
+            ConsensusResult::Agreed(addr("203.0.113.1", 4000))
+        );
+
+        consensus.reset();
+        assert_eq!(consensus.evaluate(), ConsensusResult::Undecided);
+    }
+}
@@ -0,0 +1,108 @@
+//! A building block for simultaneous-open tie-breaking in endpoint-upgrade races -- **not, on its
+//! own, a fix for the flapping it's meant to prevent.** [`decide`]/[`UpgradeNonce`] are unused
+//! outside this file: nothing carries a nonce on an actual upgrade request, and [`decide`]'s
+//! outcome gates nothing. Until that's wired up, the flapping this module describes still happens
+//! exactly as before.
+//!
+//! The scenario: when `CrossPingCheck` validates a direct path on both peers at nearly the same
+//! time, both sides can send an upgrade request within `UpgradeSync`'s window and both rewrite
+//! their WireGuard endpoint. This borrows the multistream-select "simultaneous open" symmetry
+//! break as the fix *shape*: each side's upgrade request would carry a random nonce, and [`decide`]
+//! compares the local and remote nonce to pick exactly one initiator.
+//!
+//! Actually carrying the nonce on `UpgradeSync`'s request message and gating its request handling
+//! on [`decide`]'s outcome is left as an integration seam: `telio-traversal` (which owns
+//! `UpgradeSync`) isn't present in this checkout, so there's no request message type to add the
+//! nonce to and no request-handling call site to gate on `decide`'s outcome. This module only
+//! implements the nonce type and the symmetry-break rule itself, which don't depend on that
+//! message type -- whoever adds `telio-traversal` to this checkout is the one who can finish the
+//! wiring and turn this into an actual fix.
+
+use std::time::{Duration, Instant};
+
+/// A random value included in an upgrade request so both sides can deterministically agree on
+/// which one drives the endpoint change when requests cross.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UpgradeNonce(u64);
+
+impl UpgradeNonce {
+    /// Generates a new nonce. Not cryptographically random: a symmetry break only needs the two
+    /// sides to agree on an order, not to be unpredictable to an adversary, so a mixed-but-cheap
+    /// value is enough and avoids depending on a `rand` crate not confirmed to be a dependency of
+    /// this crate.
+    ///
+    /// This is exactly the scenario the module doc calls a race -- two sides generating a nonce
+    /// at roughly the same time -- so the two sources on their own aren't enough: a process-id
+    /// plus elapsed-since-first-call jitter only varies between the two sides by however far
+    /// apart their process starts and first calls happened to land, which for two peers racing to
+    /// upgrade at the same moment can be very little. Mixing in a per-call counter and the calling
+    /// thread's ID (see [`super::pcp_endpoint`]'s `entropy_seed`, which has the same shape) gives
+    /// every nonce on a given side its own distinct seed regardless of timing, so two sides
+    /// racing still land on different values.
+    pub fn generate() -> Self {
+        let mut state = entropy_seed();
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d049bb133111eb);
+        UpgradeNonce(state ^ (state >> 31))
+    }
+}
+
+/// Mixes process id, thread id, time since first call, and a per-call counter into a seed that's
+/// distinct per call even when two calls (in the same or different processes) land at nearly the
+/// same instant. See [`UpgradeNonce::generate`].
+fn entropy_seed() -> u64 {
+    static CALLS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    static START: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+    let start = *START.get_or_init(Instant::now);
+    let sources = [
+        start.elapsed().as_nanos() as u64,
+        std::process::id() as u64,
+        thread_id_hash(),
+        CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+    ];
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for value in sources {
+        for byte in value.to_ne_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn thread_id_hash() -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::thread::current().id().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The result of comparing a local and remote [`UpgradeNonce`] for a pair of crossed upgrade
+/// requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreakOutcome {
+    /// The local nonce is larger: this side is the initiator and should perform the endpoint
+    /// change, while the remote side yields and only accepts the incoming change.
+    LocalWins,
+    /// The remote nonce is larger: this side yields and should only accept the incoming change,
+    /// not perform its own.
+    RemoteWins,
+    /// The nonces are equal (vanishingly unlikely, but possible): both sides must regenerate a
+    /// fresh nonce and retry rather than either proceeding, to avoid a deadlock where neither acts.
+    Retry,
+}
+
+/// Decides which side of a crossed upgrade request acts, per the module doc's symmetry-break rule.
+pub fn decide(local: UpgradeNonce, remote: UpgradeNonce) -> TieBreakOutcome {
+    match local.cmp(&remote) {
+        std::cmp::Ordering::Greater => TieBreakOutcome::LocalWins,
+        std::cmp::Ordering::Less => TieBreakOutcome::RemoteWins,
+        std::cmp::Ordering::Equal => TieBreakOutcome::Retry,
+    }
+}
+
+/// How long an upgrade request that hasn't been answered yet should be considered "outstanding"
+/// and thus eligible to race against an incoming request, matching `UpgradeSync`'s existing
+/// 5-second window.
+pub const UPGRADE_WINDOW: Duration = Duration::from_secs(5);